@@ -0,0 +1,93 @@
+use std::env;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::Sdl;
+
+use cm_rust::game::GameEvent;
+use cm_rust::sound::{self, Mixer};
+
+/// The keys that mute the sound and change the volume, they cannot be bound to
+/// actions.
+pub const AUDIO_KEYS: [Keycode; 6] = [
+    Keycode::M, Keycode::Minus, Keycode::KpMinus, Keycode::Equals, Keycode::Plus, Keycode::KpPlus,
+];
+
+struct MixerCallback(Mixer);
+
+impl AudioCallback for MixerCallback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.0.mix(out);
+    }
+}
+
+/// Plays the sound effects of game events and the music.  `M` mutes the
+/// sound, `-` and `+` change the volume.
+pub struct Audio {
+    /// `None` if not even the dummy audio driver could be opened.
+    device: Option<AudioDevice<MixerCallback>>,
+}
+
+impl Audio {
+    /// Opens the default audio device, or the dummy audio driver of SDL on
+    /// machines without sound.
+    pub fn open(sdl_context: &Sdl) -> Audio {
+        let device = open_device(sdl_context).or_else(|reason| {
+            eprintln!("warning: cannot open audio device ({}), using the dummy audio driver", reason);
+            env::set_var("SDL_AUDIODRIVER", "dummy");
+            open_device(sdl_context)
+        });
+        match device {
+            Ok(device) => {
+                device.resume();
+                Audio { device: Some(device) }
+            }
+            Err(reason) => {
+                eprintln!("warning: cannot open audio device ({}), sound is disabled", reason);
+                Audio { device: None }
+            }
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        let mut device = match &mut self.device {
+            Some(device) => device.lock(),
+            None => return,
+        };
+        match event {
+            Event::KeyDown { keycode: Some(Keycode::M), .. } => device.0.toggle_mute(),
+            Event::KeyDown { keycode: Some(Keycode::Minus), .. }
+            | Event::KeyDown { keycode: Some(Keycode::KpMinus), .. } => device.0.change_volume(-1),
+            Event::KeyDown { keycode: Some(Keycode::Equals), .. }
+            | Event::KeyDown { keycode: Some(Keycode::Plus), .. }
+            | Event::KeyDown { keycode: Some(Keycode::KpPlus), .. } => device.0.change_volume(1),
+            _ => {}
+        }
+    }
+
+    /// Plays the sound effects of the events of a tick.
+    pub fn play_events(&mut self, events: &[GameEvent]) {
+        if let Some(device) = &mut self.device {
+            let mut device = device.lock();
+            for sound in events.iter().filter_map(|&event| sound::sound_for_event(event)) {
+                device.0.play(sound);
+            }
+        }
+    }
+
+    pub fn play_music(&mut self, playing: bool) {
+        if let Some(device) = &mut self.device {
+            device.lock().0.music_playing = playing;
+        }
+    }
+}
+
+
+fn open_device(sdl_context: &Sdl) -> Result<AudioDevice<MixerCallback>, String> {
+    let audio_subsystem = sdl_context.audio()?;
+    let desired_spec = AudioSpecDesired { freq: Some(44_100), channels: Some(1), samples: None };
+    audio_subsystem.open_playback(None, &desired_spec, |spec| MixerCallback(Mixer::new(spec.freq as u32)))
+}
@@ -0,0 +1,143 @@
+//! The part of the level that is visible in the window.
+
+use std::ops::Range;
+
+/// The target moves freely in the middle of the view (the dead zone), the
+/// camera only follows once the target is closer than this fraction of the
+/// view size to an edge.
+const MARGIN: f32 = 0.25;
+
+/// A view of `width` x `height` tiles whose top left corner is at (x, y).  All
+/// values are in tiles and may be fractional.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    /// The column of the left edge.
+    pub x: f32,
+    /// The row of the top edge.
+    pub y: f32,
+    /// The number of visible columns.
+    pub width: f32,
+    /// The number of visible rows.
+    pub height: f32,
+}
+
+impl Camera {
+    /// A view of `width` x `height` tiles at the top left corner of the level.
+    pub fn new(width: f32, height: f32) -> Camera {
+        Camera { x: 0.0, y: 0.0, width, height }
+    }
+
+    /// Centers the view on the target, e.g., when a level starts.
+    pub fn center_on(&mut self, target_x: f32, target_y: f32, level_width: u16, level_height: u16) {
+        self.x = target_x + 0.5 - self.width / 2.0;
+        self.y = target_y + 0.5 - self.height / 2.0;
+        self.clamp(level_width, level_height);
+    }
+
+    /// Moves the view just enough to keep the target tile inside the dead
+    /// zone.
+    pub fn follow(&mut self, target_x: f32, target_y: f32, level_width: u16, level_height: u16) {
+        self.x = follow_axis(self.x, self.width, target_x);
+        self.y = follow_axis(self.y, self.height, target_y);
+        self.clamp(level_width, level_height);
+    }
+
+    /// Moves the view by the given number of tiles, e.g., in the level editor.
+    pub fn scroll(&mut self, dx: f32, dy: f32, level_width: u16, level_height: u16) {
+        self.x += dx;
+        self.y += dy;
+        self.clamp(level_width, level_height);
+    }
+
+    /// Keeps the view inside the level.  A level that is smaller than the view
+    /// is centered.
+    fn clamp(&mut self, level_width: u16, level_height: u16) {
+        self.x = clamp_axis(self.x, self.width, level_width as f32);
+        self.y = clamp_axis(self.y, self.height, level_height as f32);
+    }
+
+    /// Returns the columns and rows of all cells that are at least partly
+    /// visible.
+    pub fn visible_cells(&self, level_width: u16, level_height: u16) -> (Range<u16>, Range<u16>) {
+        let range = |start: f32, length: f32, size: u16| {
+            let first = start.floor().max(0.0).min(size as f32) as u16;
+            let last = (start + length).ceil().max(0.0).min(size as f32) as u16;
+            first..last
+        };
+        (range(self.x, self.width, level_width), range(self.y, self.height, level_height))
+    }
+}
+
+
+fn follow_axis(start: f32, length: f32, target: f32) -> f32 {
+    let margin = length * MARGIN;
+    if target < start + margin {
+        target - margin
+    } else if target + 1.0 > start + length - margin {
+        target + 1.0 - length + margin
+    } else {
+        start
+    }
+}
+
+
+fn clamp_axis(start: f32, length: f32, size: f32) -> f32 {
+    if size <= length {
+        (size - length) / 2.0
+    } else {
+        start.max(0.0).min(size - length)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_follow() {
+        let mut camera = Camera::new(8.0, 6.0);
+        camera.center_on(2.0, 2.0, 30, 20);
+        assert_eq!((camera.x, camera.y), (0.0, 0.0));
+
+        // inside the dead zone the camera does not move
+        camera.follow(4.0, 2.0, 30, 20);
+        assert_eq!((camera.x, camera.y), (0.0, 0.0));
+
+        // leaving it to the right and down scrolls
+        camera.follow(7.0, 4.0, 30, 20);
+        assert_eq!((camera.x, camera.y), (2.0, 0.5));
+
+        // the view stays inside the level
+        camera.follow(29.0, 19.0, 30, 20);
+        assert_eq!((camera.x, camera.y), (22.0, 14.0));
+        camera.center_on(0.0, 0.0, 30, 20);
+        assert_eq!((camera.x, camera.y), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut camera = Camera::new(8.0, 6.0);
+        camera.scroll(3.0, 1.0, 30, 20);
+        assert_eq!((camera.x, camera.y), (3.0, 1.0));
+        camera.scroll(-5.0, 20.0, 30, 20);
+        assert_eq!((camera.x, camera.y), (0.0, 14.0));
+        camera.scroll(1.0, 0.0, 4, 20);
+        assert_eq!((camera.x, camera.y), (-2.0, 14.0));
+    }
+
+    #[test]
+    fn test_small_level_is_centered() {
+        let mut camera = Camera::new(10.0, 6.0);
+        camera.follow(5.0, 1.0, 4, 2);
+        assert_eq!((camera.x, camera.y), (-3.0, -2.0));
+        assert_eq!(camera.visible_cells(4, 2), (0..4, 0..2));
+    }
+
+    #[test]
+    fn test_visible_cells() {
+        let camera = Camera { x: 2.5, y: 1.0, width: 4.0, height: 3.0 };
+        assert_eq!(camera.visible_cells(20, 20), (2..7, 1..4));
+        assert_eq!(camera.visible_cells(5, 3), (2..5, 1..3));
+    }
+}
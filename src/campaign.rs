@@ -0,0 +1,169 @@
+//! Playing all levels of an episode in order and remembering the progress.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::level::{self, Level};
+
+/// A campaign plays all levels of an episode in the order of their numbers.
+/// The highest level number reached so far is stored in the progress file of
+/// the episode, so the player can later start from any level they have
+/// already reached.
+pub struct Campaign {
+    directory: PathBuf,
+    /// The numbers of all levels of the episode in ascending order.
+    pub levels: Vec<u16>,
+    /// The index of the furthest level reached so far.
+    pub reached: usize,
+    /// The points of all completed levels.
+    pub score: u32,
+}
+
+impl Campaign {
+    /// Finds the levels in the directory of the episode and reads the progress
+    /// file if there is one.
+    pub fn load(directory: &Path) -> Result<Campaign, Error> {
+        let levels = find_levels(directory)?;
+        if levels.is_empty() {
+            return Err(Error::NoLevels { path: directory.into() });
+        }
+        let progress_path = directory.join("progress.dat");
+        let reached_level_number = if progress_path.exists() { load_progress(&progress_path)? } else { 0 };
+        let reached = levels.iter().rposition(|&level_number| level_number <= reached_level_number).unwrap_or(0);
+        Ok(Campaign {
+            directory: directory.into(),
+            levels,
+            reached,
+            score: 0,
+        })
+    }
+
+    /// The path of the level file at the index.
+    pub fn level_path(&self, index: usize) -> PathBuf {
+        self.directory.join(format!("LEVEL{:03}.BTN", self.levels[index]))
+    }
+
+    /// Loads the level at the index.
+    pub fn load_level(&self, index: usize) -> Result<Level, Error> {
+        level::load_level(&self.level_path(index))
+    }
+
+    /// Adds the score of the completed level and returns the index of the next
+    /// level, if there is one.  Reaching a new level is saved in the progress
+    /// file.
+    pub fn complete_level(&mut self, index: usize, score: u32) -> Result<Option<usize>, Error> {
+        self.score += score;
+        if index + 1 >= self.levels.len() {
+            return Ok(None);
+        }
+        if index + 1 > self.reached {
+            self.reached = index + 1;
+            save_progress(&self.directory.join("progress.dat"), self.levels[self.reached])?;
+        }
+        Ok(Some(index + 1))
+    }
+}
+
+
+/// Returns the numbers of all level files (`LEVEL###.BTN`) in the directory in
+/// ascending order.
+pub fn find_levels(directory: &Path) -> Result<Vec<u16>, Error> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(reason) => return Err(Error::io(directory, reason)),
+    };
+    let mut levels = vec![];
+    for entry in entries {
+        let file_name = match entry {
+            Ok(entry) => entry.file_name(),
+            Err(reason) => return Err(Error::io(directory, reason)),
+        };
+        let file_name = file_name.to_string_lossy();
+        if file_name.len() == 12 && file_name.starts_with("LEVEL") && file_name.ends_with(".BTN") {
+            if let Ok(level_number) = file_name[5..8].parse() {
+                levels.push(level_number);
+            }
+        }
+    }
+    levels.sort_unstable();
+    Ok(levels)
+}
+
+
+/// The progress file contains the highest level number reached (2 bytes,
+/// little endian).
+fn load_progress(path: &Path) -> Result<u16, Error> {
+    let buffer = match fs::read(path) {
+        Ok(buffer) => buffer,
+        Err(reason) => return Err(Error::io(path, reason)),
+    };
+    if buffer.len() != 2 {
+        return Err(Error::SizeMismatch { path: path.into(), offset: 0, expected: 2, found: buffer.len() });
+    }
+    Ok(u16::from_le_bytes([buffer[0], buffer[1]]))
+}
+
+
+fn save_progress(path: &Path, level_number: u16) -> Result<(), Error> {
+    fs::write(path, level_number.to_le_bytes()).map_err(|reason| Error::io(path, reason))
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::Write;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn create_episode(level_numbers: &[u16]) -> TempDir {
+        let directory = tempfile::tempdir().unwrap();
+        for level_number in level_numbers {
+            let path = directory.path().join(format!("LEVEL{:03}.BTN", level_number));
+            File::create(path).unwrap().write_all(&[1, 0, 1, 0, 34]).unwrap();
+        }
+        File::create(directory.path().join("TILE.DAT")).unwrap();
+        File::create(directory.path().join("LEVELXYZ.BTN")).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_find_levels() {
+        let directory = create_episode(&[3, 1, 12, 2]);
+        assert_eq!(find_levels(directory.path()).unwrap(), vec![1, 2, 3, 12]);
+    }
+
+    #[test]
+    fn test_progress() {
+        let directory = create_episode(&[1, 2, 5]);
+        let mut campaign = Campaign::load(directory.path()).unwrap();
+        assert_eq!(campaign.reached, 0);
+        assert_eq!(campaign.level_path(2), directory.path().join("LEVEL005.BTN"));
+        assert_eq!(campaign.load_level(0).unwrap().width, 1);
+
+        assert_eq!(campaign.complete_level(0, 100).unwrap(), Some(1));
+        assert_eq!(campaign.complete_level(1, 50).unwrap(), Some(2));
+        assert_eq!(campaign.complete_level(2, 20).unwrap(), None);
+        assert_eq!(campaign.score, 170);
+        assert_eq!(campaign.reached, 2);
+
+        // the progress is kept, the score is not
+        let campaign = Campaign::load(directory.path()).unwrap();
+        assert_eq!(campaign.reached, 2);
+        assert_eq!(campaign.score, 0);
+
+        // progress is not lost if a level is removed
+        fs::remove_file(directory.path().join("LEVEL005.BTN")).unwrap();
+        let campaign = Campaign::load(directory.path()).unwrap();
+        assert_eq!(campaign.reached, 1);
+    }
+
+    #[test]
+    fn test_no_levels() {
+        let directory = create_episode(&[]);
+        assert!(matches!(Campaign::load(directory.path()), Err(Error::NoLevels { path }) if path == directory.path()));
+    }
+}
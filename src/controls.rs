@@ -0,0 +1,273 @@
+//! Mapping keys and game controller buttons and axes to the actions of the
+//! game, and reading and writing these bindings.
+
+use std::fs;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::game::Input;
+
+/// What the player can do, each action sets the field of the same name of
+/// the `Input`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Going left.
+    Left,
+    /// Going right.
+    Right,
+    /// Going up.
+    Up,
+    /// Going down.
+    Down,
+    /// Clearing the neighboring cell instead of entering it.
+    Fire,
+}
+
+/// All actions in the order they are bound on the remapping screen.
+pub const ACTIONS: [Action; 5] = [Action::Left, Action::Right, Action::Up, Action::Down, Action::Fire];
+
+impl Action {
+    /// The name of the action in the controls file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Fire => "fire",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().copied().find(|action| action.name() == name)
+    }
+}
+
+/// A key, a game controller button or one direction of a game controller
+/// axis, each identified by its SDL name.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Binding {
+    /// A key.
+    Key(String),
+    /// A game controller button.
+    Button(String),
+    /// One direction of a game controller axis.
+    Axis {
+        /// The SDL name of the axis.
+        name: String,
+        /// Moving the axis in the positive direction (right or down) triggers
+        /// the binding, otherwise moving it in the negative direction.
+        positive: bool,
+    },
+}
+
+impl Binding {
+    fn is_key(&self) -> bool {
+        matches!(self, Binding::Key(_))
+    }
+}
+
+/// The bindings of all actions.  An action can have any number of bindings,
+/// a binding triggers at most one action.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Controls {
+    bindings: Vec<(Action, Binding)>,
+}
+
+impl Default for Controls {
+    /// The arrow keys and space, and the D-pad, left stick and A button of a
+    /// game controller.
+    fn default() -> Controls {
+        let key = |name: &str| Binding::Key(name.into());
+        let button = |name: &str| Binding::Button(name.into());
+        let axis = |name: &str, positive| Binding::Axis { name: name.into(), positive };
+        Controls {
+            bindings: vec![
+                (Action::Left, key("Left")),
+                (Action::Right, key("Right")),
+                (Action::Up, key("Up")),
+                (Action::Down, key("Down")),
+                (Action::Fire, key("Space")),
+                (Action::Left, button("dpleft")),
+                (Action::Right, button("dpright")),
+                (Action::Up, button("dpup")),
+                (Action::Down, button("dpdown")),
+                (Action::Fire, button("a")),
+                (Action::Left, axis("leftx", false)),
+                (Action::Right, axis("leftx", true)),
+                (Action::Up, axis("lefty", false)),
+                (Action::Down, axis("lefty", true)),
+            ],
+        }
+    }
+}
+
+impl Controls {
+    /// All bindings of the action.
+    pub fn bindings(&self, action: Action) -> impl Iterator<Item = &Binding> {
+        self.bindings.iter().filter(move |(a, _)| *a == action).map(|(_, binding)| binding)
+    }
+
+    /// Binds the action to a key or a controller input, replacing its other
+    /// bindings of the same kind (keyboard or controller).  The binding is
+    /// removed from all other actions.
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        self.bindings.retain(|(a, b)| *b != binding && (*a != action || b.is_key() != binding.is_key()));
+        self.bindings.push((action, binding));
+    }
+
+    /// Removes the binding from the action it is bound to.
+    pub fn unbind(&mut self, binding: &Binding) {
+        self.bindings.retain(|(_, b)| b != binding);
+    }
+
+    /// Returns the input of all actions that have at least one active binding.
+    pub fn input(&self, is_active: impl Fn(&Binding) -> bool) -> Input {
+        let mut input = Input::default();
+        for (action, binding) in &self.bindings {
+            if !is_active(binding) {
+                continue;
+            }
+            match action {
+                Action::Left => input.left = true,
+                Action::Right => input.right = true,
+                Action::Up => input.up = true,
+                Action::Down => input.down = true,
+                Action::Fire => input.fire = true,
+            }
+        }
+        input
+    }
+}
+
+
+/**
+ * The controls file has a line for every action, listing its bindings
+ * separated by commas, e.g.:
+ *
+ * ```text
+ * left = key:Left, button:dpleft, axis:-leftx
+ * ```
+ *
+ * Empty lines and lines starting with `#` are ignored.
+ */
+fn parse(path: &Path, text: &str) -> Result<Controls, Error> {
+    let mut bindings = vec![];
+    for (line_number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, values) = match line.find('=') {
+            Some(position) => (line[..position].trim(), line[position + 1..].trim()),
+            None => return Err(Error::syntax(path, line_number, "expected action = bindings")),
+        };
+        let action = match Action::from_name(name) {
+            Some(action) => action,
+            None => return Err(Error::syntax(path, line_number, &format!("unknown action {}", name))),
+        };
+        for value in values.split(',').map(str::trim).filter(|value| !value.is_empty()) {
+            let binding = match value.find(':').map(|position| (&value[..position], &value[position + 1..])) {
+                Some(("key", name)) => Binding::Key(name.into()),
+                Some(("button", name)) => Binding::Button(name.into()),
+                Some(("axis", name)) if name.starts_with('+') => Binding::Axis { name: name[1..].into(), positive: true },
+                Some(("axis", name)) if name.starts_with('-') => Binding::Axis { name: name[1..].into(), positive: false },
+                _ => return Err(Error::syntax(path, line_number, &format!("invalid binding {}", value))),
+            };
+            bindings.push((action, binding));
+        }
+    }
+    Ok(Controls { bindings })
+}
+
+
+fn serialize(controls: &Controls) -> String {
+    let mut text = String::new();
+    for &action in &ACTIONS {
+        let values: Vec<String> = controls.bindings(action).map(|binding| match binding {
+            Binding::Key(name) => format!("key:{}", name),
+            Binding::Button(name) => format!("button:{}", name),
+            Binding::Axis { name, positive } => format!("axis:{}{}", if *positive { '+' } else { '-' }, name),
+        }).collect();
+        text.push_str(&format!("{} = {}\n", action.name(), values.join(", ")));
+    }
+    text
+}
+
+
+/// Reads the controls file.
+pub fn load(path: &Path) -> Result<Controls, Error> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse(path, &text),
+        Err(reason) => Err(Error::io(path, reason)),
+    }
+}
+
+
+/// Writes the controls file, one line per action.
+pub fn save(path: &Path, controls: &Controls) -> Result<(), Error> {
+    fs::write(path, serialize(controls)).map_err(|reason| Error::io(path, reason))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_input() {
+        let controls = Controls::default();
+        let input = controls.input(|binding| {
+            *binding == Binding::Key("Space".into()) || *binding == Binding::Axis { name: "leftx".into(), positive: true }
+        });
+        assert_eq!(input, Input { right: true, fire: true, ..Input::default() });
+        assert_eq!(controls.input(|_| false), Input::default());
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut controls = Controls::default();
+        controls.bind(Action::Fire, Binding::Key("Left Ctrl".into()));
+        controls.bind(Action::Left, Binding::Key("Space".into()));
+        assert_eq!(controls.bindings(Action::Fire).collect::<Vec<_>>(), vec![&Binding::Button("a".into()), &Binding::Key("Left Ctrl".into())]);
+        assert_eq!(
+            controls.bindings(Action::Left).collect::<Vec<_>>(),
+            vec![&Binding::Button("dpleft".into()), &Binding::Axis { name: "leftx".into(), positive: false }, &Binding::Key("Space".into())],
+        );
+    }
+
+    #[test]
+    fn test_unbind() {
+        let mut controls = Controls::default();
+        controls.unbind(&Binding::Key("Space".into()));
+        assert_eq!(controls.bindings(Action::Fire).collect::<Vec<_>>(), vec![&Binding::Button("a".into())]);
+    }
+
+    #[test]
+    fn test_load_save() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("controls.cfg");
+        let controls = Controls::default();
+        save(&path, &controls).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("left = key:Left, button:dpleft, axis:-leftx\n"));
+
+        let mut loaded = load(&path).unwrap();
+        // the order of the bindings is only kept per action
+        loaded.bindings.sort_by_key(|(action, _)| ACTIONS.iter().position(|a| a == action));
+        let mut expected = controls;
+        expected.bindings.sort_by_key(|(action, _)| ACTIONS.iter().position(|a| a == action));
+        assert_eq!(loaded, expected);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let path = Path::new("controls.cfg");
+        assert_eq!(parse(path, "# empty\n\nfire =").unwrap().bindings, vec![]);
+        assert_eq!(parse(path, "jump = key:Up").unwrap_err().to_string(), "controls.cfg:1: unknown action jump");
+        assert!(matches!(
+            parse(path, "left = key:Left\nright = axis:leftx"),
+            Err(Error::Syntax { line: 2, message, .. }) if message == "invalid binding axis:leftx"
+        ));
+        assert!(matches!(parse(path, "left"), Err(Error::Syntax { line: 1, .. })));
+    }
+}
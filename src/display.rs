@@ -0,0 +1,88 @@
+//! Scaling the logical resolution of the frontend to a window of any size.
+
+/// How the logical resolution is scaled to the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scaling {
+    /// Every logical pixel covers the same number of window pixels.
+    Integer,
+    /// The logical resolution fills as much of the window as possible.
+    Fractional,
+}
+
+/// The part of the window (in window pixels) that shows the logical
+/// resolution, the rest of the window shows bars.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// The left edge.
+    pub x: i32,
+    /// The top edge.
+    pub y: i32,
+    /// The width of the scaled logical resolution.
+    pub width: u32,
+    /// The height of the scaled logical resolution.
+    pub height: u32,
+    /// The number of window pixels per logical pixel in either direction.
+    pub scale: f32,
+}
+
+impl Viewport {
+    /// Centers the logical resolution in the window, both given as width and
+    /// height in pixels, keeping its aspect ratio.  Integer scaling falls back
+    /// to fractional scaling if the window is smaller than the logical
+    /// resolution.
+    pub fn fit(logical: (u32, u32), window: (u32, u32), scaling: Scaling) -> Viewport {
+        let mut scale = (window.0 as f32 / logical.0 as f32).min(window.1 as f32 / logical.1 as f32);
+        if scaling == Scaling::Integer && scale >= 1.0 {
+            scale = scale.floor();
+        }
+        let width = (logical.0 as f32 * scale).round() as u32;
+        let height = (logical.1 as f32 * scale).round() as u32;
+        Viewport {
+            x: (window.0 as i32 - width as i32) / 2,
+            y: (window.1 as i32 - height as i32) / 2,
+            width,
+            height,
+            scale,
+        }
+    }
+
+    /// Converts a position in the window, e.g., of the mouse, to logical
+    /// pixels.  Positions on the bars lie outside of the logical resolution.
+    pub fn to_logical(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            ((x - self.x) as f32 / self.scale).floor() as i32,
+            ((y - self.y) as f32 / self.scale).floor() as i32,
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(
+            Viewport::fit((600, 400), (1500, 1100), Scaling::Integer),
+            Viewport { x: 150, y: 150, width: 1200, height: 800, scale: 2.0 },
+        );
+        assert_eq!(
+            Viewport::fit((600, 400), (1500, 1100), Scaling::Fractional),
+            Viewport { x: 0, y: 50, width: 1500, height: 1000, scale: 2.5 },
+        );
+        // a window smaller than the logical resolution is never left empty
+        assert_eq!(
+            Viewport::fit((600, 400), (300, 300), Scaling::Integer),
+            Viewport { x: 0, y: 50, width: 300, height: 200, scale: 0.5 },
+        );
+    }
+
+    #[test]
+    fn test_to_logical() {
+        let viewport = Viewport::fit((600, 400), (1500, 1100), Scaling::Integer);
+        assert_eq!(viewport.to_logical(150, 150), (0, 0));
+        assert_eq!(viewport.to_logical(1349, 949), (599, 399));
+        assert_eq!(viewport.to_logical(100, 100), (-25, -25));
+    }
+}
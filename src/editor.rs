@@ -0,0 +1,270 @@
+use std::path::Path;
+use std::time::Duration;
+
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, WindowCanvas};
+use sdl2::EventPump;
+
+use cm_rust::camera::Camera;
+use cm_rust::game::Game;
+use cm_rust::level::{self, Cell, Direction, Level, BACKGROUND};
+use cm_rust::objects::{GemKind, KeyColor, Object, PlayerAction, LETTERS};
+use crate::audio::Audio;
+use crate::input::InputDevices;
+use crate::screen::Screen;
+use crate::{draw_level, draw_object, draw_tile, exit_on_error, run_level, Devices};
+
+/// The objects that can be painted, `None` erases a cell.
+const PALETTE: [Option<Object>; 22] = [
+    None,
+    Some(Object::Dirt { north: false, east: false, south: false, west: false, generic: true }),
+    Some(Object::Wall { north: false, east: false, south: false, west: false, generic: true }),
+    Some(Object::Brick),
+    Some(Object::Door { open: false, frame_offset: 0 }),
+    Some(Object::Key { color: KeyColor::Yellow, frame_offset: 0 }),
+    Some(Object::Key { color: KeyColor::Red, frame_offset: 0 }),
+    Some(Object::Key { color: KeyColor::Grey, frame_offset: 0 }),
+    Some(Object::Lock { color: KeyColor::Yellow }),
+    Some(Object::Lock { color: KeyColor::Red }),
+    Some(Object::Lock { color: KeyColor::Grey }),
+    Some(Object::Pickaxe { frame_offset: 0 }),
+    Some(Object::Gem { kind: GemKind::Gem1, frame_offset: 0 }),
+    Some(Object::Gem { kind: GemKind::Gem2, frame_offset: 0 }),
+    Some(Object::Gem { kind: GemKind::Gem3, frame_offset: 0 }),
+    Some(Object::Boulder),
+    Some(Object::Balloon),
+    Some(Object::AirshipLeft { frame_offset: 0 }),
+    Some(Object::AirshipRight { frame_offset: 0 }),
+    Some(Object::Player { action: PlayerAction::Idle, frame_offset: 0 }),
+    Some(Object::Enemy { facing: Direction::West, frame_offset: 0 }),
+    Some(Object::Letter { char: 'A' }),
+];
+
+/// The largest width and height of a level in the editor.
+const MAX_LEVEL_SIZE: u16 = 256;
+
+/// The level editor shows the level and a palette of all objects in the
+/// bottom row of the window.  The selected object is painted with the left
+/// mouse button and cells are erased with the right mouse button.  The mouse
+/// wheel chooses the character of the letter tile.  The arrow keys change the
+/// size of the level and scroll the view while control is held, `P` plays the
+/// level, `S` saves it and escape quits (twice if there are unsaved changes).
+pub fn edit(canvas: &mut WindowCanvas, screen: &mut Screen, event_pump: &mut EventPump, input: &mut InputDevices, audio: &mut Audio, tile_textures: &[(u16, u16, Texture)], path: &Path) {
+    let mut level = if path.exists() {
+        exit_on_error(level::load_level(path))
+    } else {
+        Level::new(20, 15)
+    };
+    let (tile_width, tile_height) = (tile_textures[BACKGROUND as usize].0 as i32, tile_textures[BACKGROUND as usize].1 as i32);
+    let palette_row = (screen.size().1 as i32 / tile_height - 1) as u16;
+    // The level is shown above the palette.
+    let mut camera = Camera::new(screen.size().0 as f32 / tile_width as f32, palette_row as f32);
+    camera.scroll(0.0, 0.0, level.width, level.height);
+    let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+
+    let mut selected = 1;
+    let mut letter = 0;
+    let mut modified = false;
+    let mut quit_requested = false;
+    let mut tick_number = 0u8;
+    loop {
+        let mut play_requested = false;
+        for event in event_pump.poll_iter() {
+            screen.handle_event(canvas, &event);
+            input.handle_event(&event);
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    if !modified || quit_requested {
+                        return;
+                    }
+                    println!("{} has unsaved changes, press escape again to quit", file_name);
+                    quit_requested = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::S), .. } => {
+                    match level::save_level(path, &level) {
+                        Ok(()) => {
+                            println!("saved {}", path.display());
+                            modified = false;
+                        }
+                        Err(reason) => eprintln!("{}", reason),
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::P), .. } => {
+                    play_requested = true;
+                }
+                Event::KeyDown { keycode: Some(keycode @ Keycode::Left), keymod, .. }
+                | Event::KeyDown { keycode: Some(keycode @ Keycode::Right), keymod, .. }
+                | Event::KeyDown { keycode: Some(keycode @ Keycode::Up), keymod, .. }
+                | Event::KeyDown { keycode: Some(keycode @ Keycode::Down), keymod, .. }
+                    if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let (dx, dy) = match keycode {
+                        Keycode::Left => (-1.0, 0.0),
+                        Keycode::Right => (1.0, 0.0),
+                        Keycode::Up => (0.0, -1.0),
+                        _ => (0.0, 1.0),
+                    };
+                    camera.scroll(dx, dy, level.width, level.height);
+                }
+                Event::KeyDown { keycode: Some(keycode @ Keycode::Left), .. }
+                | Event::KeyDown { keycode: Some(keycode @ Keycode::Right), .. }
+                | Event::KeyDown { keycode: Some(keycode @ Keycode::Up), .. }
+                | Event::KeyDown { keycode: Some(keycode @ Keycode::Down), .. } => {
+                    let (width, height) = match keycode {
+                        Keycode::Left => (level.width.saturating_sub(1).max(1), level.height),
+                        Keycode::Right if level.width < MAX_LEVEL_SIZE => (level.width + 1, level.height),
+                        Keycode::Right => (level.width, level.height),
+                        Keycode::Up => (level.width, level.height.saturating_sub(1).max(1)),
+                        _ if level.height < MAX_LEVEL_SIZE => (level.width, level.height + 1),
+                        _ => (level.width, level.height),
+                    };
+                    if (width, height) != (level.width, level.height) {
+                        level.resize(width, height);
+                        camera.scroll(0.0, 0.0, width, height);
+                        modified = true;
+                    }
+                }
+                Event::MouseButtonDown { x, y, mouse_btn, .. } => {
+                    let (x, y) = match screen_position(screen, x, y) {
+                        Some(position) => position,
+                        None => continue,
+                    };
+                    if y / tile_height == palette_row as i32 {
+                        if ((x / tile_width) as usize) < PALETTE.len() {
+                            selected = (x / tile_width) as usize;
+                        }
+                    } else if let Some((x, y)) = cell_position(&camera, x, y, tile_width, tile_height) {
+                        if paint(&mut level, x, y, mouse_btn, palette_object(selected, letter)) {
+                            modified = true;
+                        }
+                    }
+                }
+                Event::MouseMotion { x, y, mousestate, .. } => {
+                    let (x, y) = match screen_position(screen, x, y) {
+                        Some(position) => position,
+                        None => continue,
+                    };
+                    let mouse_btn = if mousestate.left() {
+                        MouseButton::Left
+                    } else if mousestate.right() {
+                        MouseButton::Right
+                    } else {
+                        continue;
+                    };
+                    if y / tile_height == palette_row as i32 {
+                        continue;
+                    }
+                    if let Some((x, y)) = cell_position(&camera, x, y, tile_width, tile_height) {
+                        if paint(&mut level, x, y, mouse_btn, palette_object(selected, letter)) {
+                            modified = true;
+                        }
+                    }
+                }
+                Event::MouseWheel { y, .. } if matches!(PALETTE[selected], Some(Object::Letter { .. })) => {
+                    letter = (letter as i32 + LETTERS.len() as i32 + y.signum()) as usize % LETTERS.len();
+                }
+                _ => {}
+            }
+        }
+        if play_requested {
+            play_test(canvas, screen, event_pump, input, audio, tile_textures, &level);
+        }
+
+        let title = format!("{}{}", file_name, if modified { "*" } else { "" });
+        if canvas.window().title() != title {
+            canvas.window_mut().set_title(&title).unwrap();
+        }
+
+        screen.clear(canvas);
+        draw_level(canvas, tile_textures, &level, &level, 0.0, &camera, tick_number);
+        // The palette row covers the cells below the view.
+        let draw_color = canvas.draw_color();
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.fill_rect(Rect::new(0, palette_row as i32 * tile_height, screen.size().0, tile_height as u32)).unwrap();
+        canvas.set_draw_color(draw_color);
+        for i in 0..PALETTE.len() {
+            draw_tile(canvas, tile_textures, i as u16, palette_row, (0.0, 0.0), BACKGROUND as usize);
+            if let Some(object) = palette_object(i, letter) {
+                draw_object(canvas, tile_textures, i as u16, palette_row, (0.0, 0.0), object, tick_number);
+            }
+        }
+        let draw_color = canvas.draw_color();
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.draw_rect(Rect::new(
+            selected as i32 * tile_width,
+            palette_row as i32 * tile_height,
+            tile_width as u32,
+            tile_height as u32,
+        )).unwrap();
+        canvas.set_draw_color(draw_color);
+        canvas.present();
+        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 15));
+        tick_number = (tick_number + 1) % 12;
+    }
+}
+
+
+/// Returns the logical position of the mouse position, or `None` if it is on
+/// the bars around the logical resolution.
+fn screen_position(screen: &Screen, x: i32, y: i32) -> Option<(i32, i32)> {
+    let (x, y) = screen.to_logical(x, y);
+    let (width, height) = screen.size();
+    if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+        return None;
+    }
+    Some((x, y))
+}
+
+
+/// Returns the cell of the level at the logical position, or `None` if it is
+/// left of or above the level.
+fn cell_position(camera: &Camera, x: i32, y: i32, tile_width: i32, tile_height: i32) -> Option<(u16, u16)> {
+    let cell_x = (camera.x + x as f32 / tile_width as f32).floor();
+    let cell_y = (camera.y + y as f32 / tile_height as f32).floor();
+    if cell_x < 0.0 || cell_y < 0.0 {
+        return None;
+    }
+    Some((cell_x as u16, cell_y as u16))
+}
+
+
+/// Returns the object of the palette entry, the letter tile shows the
+/// character at index `letter` of `LETTERS`.
+fn palette_object(index: usize, letter: usize) -> Option<Object> {
+    match PALETTE[index] {
+        Some(Object::Letter { .. }) => Some(Object::Letter { char: LETTERS[letter] as char }),
+        object => object,
+    }
+}
+
+
+/// Paints the selected palette object with the left mouse button or erases
+/// the cell with the right mouse button.  Returns whether the level has been
+/// changed.
+fn paint(level: &mut Level, x: u16, y: u16, mouse_btn: MouseButton, selected: Option<Object>) -> bool {
+    let object = match mouse_btn {
+        MouseButton::Left => selected,
+        MouseButton::Right => None,
+        _ => return false,
+    };
+    match level.cell_checked(x, y) {
+        Some(cell) if cell.object != object => {
+            level.set_cell(x, y, Cell::new(x, y, object));
+            true
+        }
+        _ => false,
+    }
+}
+
+
+/// Plays the level as it is currently edited until it is completed, the
+/// GAME OVER screen is left, or escape is pressed.
+fn play_test(canvas: &mut WindowCanvas, screen: &mut Screen, event_pump: &mut EventPump, input: &mut InputDevices, audio: &mut Audio, tile_textures: &[(u16, u16, Texture)], level: &Level) {
+    let mut game = Game::new(level.clone());
+    let mut devices = Devices { event_pump, input, audio };
+    run_level(canvas, screen, &mut devices, tile_textures, &mut game, &|| level.clone(), 0);
+}
@@ -0,0 +1,188 @@
+//! The error type of all loaders and writers.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The errors of loading and saving the files of an episode.  Errors in a file
+/// carry its path and, for binary files, the byte offset of the problem.
+#[derive(Debug)]
+pub enum Error {
+    /// The file cannot be opened, read, created or written.
+    Io {
+        /// The path of the file.
+        path: PathBuf,
+        /// The error reported by the operating system.
+        source: io::Error,
+    },
+    /// The file ends before the header starting at the offset is complete.
+    TruncatedHeader {
+        /// The path of the file.
+        path: PathBuf,
+        /// The byte offset of the header.
+        offset: usize,
+    },
+    /// The data starting at the offset has another length than its header or
+    /// the format demands.
+    SizeMismatch {
+        /// The path of the file.
+        path: PathBuf,
+        /// The byte offset of the data.
+        offset: usize,
+        /// The length in bytes the data should have.
+        expected: usize,
+        /// The length in bytes the data has.
+        found: usize,
+    },
+    /// A level file contains a byte that is no known tile number.
+    InvalidTileNumber {
+        /// The path of the file.
+        path: PathBuf,
+        /// The byte offset of the tile number.
+        offset: usize,
+        /// The unknown tile number.
+        tile_number: u8,
+    },
+    /// The length of a palette file is not divisible by 3.
+    InvalidPaletteLength {
+        /// The path of the file.
+        path: PathBuf,
+        /// The length of the file in bytes.
+        length: usize,
+    },
+    /// A palette file contains a value larger than 63.
+    InvalidPaletteValue {
+        /// The path of the file.
+        path: PathBuf,
+        /// The byte offset of the value.
+        offset: usize,
+        /// The value that is too large.
+        value: u8,
+    },
+    /// A high score file contains a name with characters other than A to Z.
+    InvalidHighScoreName {
+        /// The path of the file.
+        path: PathBuf,
+        /// The byte offset of the name.
+        offset: usize,
+    },
+    /// A name entered for the high score table is not three letters from A to
+    /// Z.
+    InvalidName {
+        /// The name as it was entered.
+        name: String,
+    },
+    /// A line of a text file (tile sheet index, GIMP or JASC palette) cannot be
+    /// parsed.
+    Syntax {
+        /// The path of the file.
+        path: PathBuf,
+        /// The number of the line, starting at 1.
+        line: usize,
+        /// What is wrong with the line.
+        message: String,
+    },
+    /// A PNG file cannot be encoded or decoded.
+    Png {
+        /// The path of the file.
+        path: PathBuf,
+        /// The error reported by the PNG library.
+        message: String,
+    },
+    /// A tile of the index lies outside of the sprite sheet.
+    TileOutsideSheet {
+        /// The path of the sprite sheet.
+        path: PathBuf,
+        /// The number of the tile.
+        tile_number: usize,
+    },
+    /// A tile uses a color that the palette does not contain.
+    MissingColor {
+        /// The index of the color in the palette.
+        color_index: u8,
+    },
+    /// The directory of an episode contains no level files.
+    NoLevels {
+        /// The path of the directory.
+        path: PathBuf,
+    },
+}
+
+impl Error {
+    /// The error of an I/O operation on the file at `path`.
+    pub fn io(path: &Path, source: io::Error) -> Error {
+        Error::Io { path: path.into(), source }
+    }
+
+    /// The error of the line with the number `line` (starting at 1) of the
+    /// text file at `path`.
+    pub fn syntax(path: &Path, line: usize, message: &str) -> Error {
+        Error::Syntax { path: path.into(), line, message: message.into() }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::TruncatedHeader { path, offset } => {
+                write!(f, "{}: header at offset {} is truncated", path.display(), offset)
+            }
+            Error::SizeMismatch { path, offset, expected, found } => write!(
+                f, "{}: data at offset {} should contain {} bytes, found {} bytes",
+                path.display(), offset, expected, found,
+            ),
+            Error::InvalidTileNumber { path, offset, tile_number } => {
+                write!(f, "{}: invalid tile number {} at offset {}", path.display(), tile_number, offset)
+            }
+            Error::InvalidPaletteLength { path, length } => write!(
+                f, "{}: length has to be divisible by 3, was {}", path.display(), length,
+            ),
+            Error::InvalidPaletteValue { path, offset, value } => write!(
+                f, "{}: invalid value {} at offset {}, expected 0 to 63", path.display(), value, offset,
+            ),
+            Error::InvalidHighScoreName { path, offset } => {
+                write!(f, "{}: name at offset {} contains invalid characters", path.display(), offset)
+            }
+            Error::InvalidName { name } => write!(f, "invalid name {:?}, expected three letters from A to Z", name),
+            Error::Syntax { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            Error::Png { path, message } => write!(f, "{}: {}", path.display(), message),
+            Error::TileOutsideSheet { path, tile_number } => {
+                write!(f, "{}: tile {} lies outside of the sheet", path.display(), tile_number)
+            }
+            Error::MissingColor { color_index } => write!(f, "palette has no color {}", color_index),
+            Error::NoLevels { path } => write!(f, "no level files found in {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let path = Path::new("MINING").join("LEVEL001.BTN");
+        assert_eq!(
+            Error::SizeMismatch { path: path.clone(), offset: 0, expected: 8, found: 5 }.to_string(),
+            format!("{}: data at offset 0 should contain 8 bytes, found 5 bytes", path.display()),
+        );
+        assert_eq!(
+            Error::InvalidTileNumber { path: path.clone(), offset: 6, tile_number: 250 }.to_string(),
+            format!("{}: invalid tile number 250 at offset 6", path.display()),
+        );
+        let error = Error::io(&path, io::Error::new(io::ErrorKind::NotFound, "not found"));
+        assert_eq!(error.to_string(), format!("{}: not found", path.display()));
+        assert!(std::error::Error::source(&error).is_some());
+    }
+}
@@ -0,0 +1,1149 @@
+//! The headless game simulation.
+
+use crate::level::{Cell, Direction, Level};
+use crate::objects::{GemKind, KeyColor, Object, PlayerAction};
+
+/// The input of a single tick, independent of where it comes from (keyboard,
+/// tests, bots or replays).
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Input {
+    /// Left is pressed.
+    pub left: bool,
+    /// Right is pressed.
+    pub right: bool,
+    /// Up is pressed.
+    pub up: bool,
+    /// Down is pressed.
+    pub down: bool,
+    /// Fire is pressed, the player clears the neighboring cell in the
+    /// direction instead of entering it.
+    pub fire: bool,
+}
+
+impl Input {
+    /// Returns the direction the player wants to go.  If multiple directions
+    /// are pressed, horizontal movement wins over vertical movement.
+    pub fn direction(self) -> Option<Direction> {
+        if self.left {
+            Some(Direction::West)
+        } else if self.right {
+            Some(Direction::East)
+        } else if self.up {
+            Some(Direction::North)
+        } else if self.down {
+            Some(Direction::South)
+        } else {
+            None
+        }
+    }
+}
+
+/// Something that happened during a tick, e.g., to trigger sounds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    /// The player has dug through dirt.
+    Dug,
+    /// A falling object has landed.
+    Landed {
+        /// The object that has landed.
+        object: Object,
+    },
+    /// The player has collected a gem.
+    GemCollected {
+        /// The kind of the gem.
+        kind: GemKind,
+    },
+    /// The player has picked up a key.
+    KeyCollected {
+        /// The color of the key.
+        color: KeyColor,
+    },
+    /// The player has opened a lock with a key.
+    LockOpened {
+        /// The color of the lock.
+        color: KeyColor,
+    },
+    /// The player has picked up a pickaxe.
+    PickaxeCollected,
+    /// The player has broken an object with a pickaxe.
+    Broken {
+        /// The object that has been broken.
+        object: Object,
+    },
+    /// An airship has crashed into the player or an enemy.
+    Exploded,
+    /// All gems have been collected and the doors have opened.
+    DoorOpened,
+    /// The level has been completed.
+    LevelCompleted,
+    /// The player has been killed.
+    PlayerDied,
+    /// An enemy has been killed.
+    EnemyKilled,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+/// How far the level has been played.
+pub enum Status {
+    /// The level is being played.
+    Running,
+    /// The player has reached the open door or, in levels without a door, all
+    /// gems have been collected.
+    Completed,
+    /// The player has been crushed or caught by an enemy at (x, y).  The level
+    /// goes on for a few ticks while the death animation is shown.
+    Dying {
+        /// The column of the player.
+        x: u16,
+        /// The row of the player.
+        y: u16,
+        /// The number of ticks until the animation is over.
+        ticks_left: u8,
+    },
+    /// The death animation is over and the level has to be restarted.
+    Died,
+    /// The player has died without any lives left.
+    GameOver,
+}
+
+/// The lives the player starts a game with.
+pub const INITIAL_LIVES: u8 = 3;
+
+/// The number of ticks the death animation lasts.
+pub const DEATH_ANIMATION_TICKS: u8 = 24;
+
+/// The number of bricks or walls a single pickaxe can break.
+pub const PICKAXE_USES: u8 = 3;
+
+/// The items the player carries around.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Inventory {
+    /// The number of yellow keys.
+    pub yellow_keys: u8,
+    /// The number of red keys.
+    pub red_keys: u8,
+    /// The number of grey keys.
+    pub grey_keys: u8,
+    /// The number of bricks or walls the pickaxes can still break.
+    pub pickaxe_uses: u8,
+}
+
+impl Inventory {
+    /// The number of keys of the given color.
+    pub fn keys(&self, color: KeyColor) -> u8 {
+        match color {
+            KeyColor::Yellow => self.yellow_keys,
+            KeyColor::Red => self.red_keys,
+            KeyColor::Grey => self.grey_keys,
+        }
+    }
+
+    fn keys_mut(&mut self, color: KeyColor) -> &mut u8 {
+        match color {
+            KeyColor::Yellow => &mut self.yellow_keys,
+            KeyColor::Red => &mut self.red_keys,
+            KeyColor::Grey => &mut self.grey_keys,
+        }
+    }
+
+    /// Removes a key of the given color and returns whether there was one.
+    pub fn use_key(&mut self, color: KeyColor) -> bool {
+        let keys = self.keys_mut(color);
+        if *keys > 0 {
+            *keys -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A game owns a level and advances it tick by tick.  It does not know
+/// anything about rendering, so it can be driven without opening a window.
+pub struct Game {
+    /// The level in its current state.
+    pub level: Level,
+    /// How far the level has been played.
+    pub status: Status,
+    /// The lives left, including the one being played.
+    pub lives: u8,
+    /// The points collected in this level.
+    pub score: u32,
+    /// The number of gems collected in this level.
+    pub gems_collected: u32,
+    /// The number of gems the level started with.
+    pub gems_total: u32,
+    /// The items the player carries.
+    pub inventory: Inventory,
+    /// The number of ticks the level has been played.
+    pub ticks: u32,
+    /// Doors are unlocked as soon as all gems have been collected.
+    pub doors_open: bool,
+    has_door: bool,
+    /// The number of consecutive ticks the player has been pushing without
+    /// moving the object yet.
+    push_ticks: u8,
+    /// The events of the last tick.
+    pub events: Vec<GameEvent>,
+}
+
+impl Game {
+    /// Starts the level with the initial lives.
+    pub fn new(mut level: Level) -> Game {
+        level.update_neighbors();
+        let gems_total = level.cells()
+            .filter(|cell| matches!(cell.object, Some(Object::Gem { .. })))
+            .count() as u32;
+        let has_door = level.cells().any(|cell| matches!(cell.object, Some(Object::Door { .. })));
+        Game {
+            level,
+            status: Status::Running,
+            lives: INITIAL_LIVES,
+            score: 0,
+            gems_collected: 0,
+            gems_total,
+            inventory: Inventory::default(),
+            ticks: 0,
+            doors_open: false,
+            has_door,
+            push_ticks: 0,
+            events: vec![],
+        }
+    }
+
+    /// Starts a level with the lives that are left, i.e., the same level over
+    /// after the player has died or the next level.  Everything collected in
+    /// the current level is lost.
+    pub fn start_level(&mut self, level: Level) {
+        *self = Game { lives: self.lives, ..Game::new(level) };
+    }
+
+    /// The number of gems that still have to be collected.
+    pub fn gems_remaining(&self) -> u32 {
+        self.gems_total - self.gems_collected
+    }
+
+    /// Returns the position of the player or `None` if the player is dead.
+    pub fn player_position(&self) -> Option<(u16, u16)> {
+        self.level.cells()
+            .find(|cell| matches!(cell.object, Some(Object::Player { .. })))
+            .map(|cell| (cell.x, cell.y))
+    }
+
+    /// Advances the game by one tick.  Nothing happens anymore once the level
+    /// has ended.
+    pub fn step(&mut self, input: Input) {
+        self.events.clear();
+        match self.status {
+            Status::Running => {}
+            Status::Dying { ticks_left: 0, .. } => {
+                self.status = if self.lives > 0 { Status::Died } else { Status::GameOver };
+                return;
+            }
+            Status::Dying { x, y, ticks_left } => self.status = Status::Dying { x, y, ticks_left: ticks_left - 1 },
+            Status::Completed | Status::Died | Status::GameOver => return,
+        }
+        self.ticks += 1;
+        self.finish_movements();
+        self.move_player(input);
+        self.move_enemies();
+        self.move_airships();
+        self.apply_gravity();
+        self.check_enemy_contact();
+        self.reset_changed_in_current_tick();
+        if self.status == Status::Running {
+            self.check_completion();
+        }
+    }
+
+    fn check_completion(&mut self) {
+        if self.has_door {
+            if !self.doors_open && self.gems_remaining() == 0 {
+                self.open_doors();
+            }
+        } else if self.gems_total > 0 && self.gems_remaining() == 0 {
+            self.complete();
+        }
+    }
+
+    fn open_doors(&mut self) {
+        for cell in self.level.map.iter_mut() {
+            if let Some(Object::Door { frame_offset, .. }) = cell.object {
+                cell.object = Some(Object::Door { open: true, frame_offset });
+            }
+        }
+        self.doors_open = true;
+        self.events.push(GameEvent::DoorOpened);
+    }
+
+    fn complete(&mut self) {
+        self.status = Status::Completed;
+        self.events.push(GameEvent::LevelCompleted);
+    }
+
+    /// Objects that started moving in the previous tick arrive in their new
+    /// cell and the cells they left become free.  They do not move again in
+    /// the current tick.
+    fn finish_movements(&mut self) {
+        for y in 0..self.level.height {
+            for x in 0..self.level.width {
+                if let Some(&cell) = self.level.cell(x, y) {
+                    self.level.set_cell(x, y, Cell {
+                        pre_occupied: false,
+                        post_occupied: false,
+                        changed_in_current_tick: cell.moving_in_from.is_some(),
+                        moving_in_from: None,
+                        ..cell
+                    })
+                }
+            }
+        }
+    }
+
+    /// Starts moving the object at (x, y) to its neighbor in the given
+    /// direction.  The old cell stays occupied until the movement is finished
+    /// in the next tick.
+    fn move_object(&mut self, x: u16, y: u16, direction: Direction, falling: bool) {
+        if let Some(&cell) = self.level.cell(x, y) {
+            if let Some((x_new, y_new)) = self.level.neighbor_position(x, y, direction) {
+                if let Some(&neighbor) = self.level.cell(x_new, y_new) {
+                    self.level.set_cell(x, y, Cell {
+                        object: None,
+                        post_occupied: true,
+                        changed_in_current_tick: true,
+                        falling: false,
+                        ..cell
+                    });
+                    self.level.set_cell(x_new, y_new, Cell {
+                        object: cell.object,
+                        pre_occupied: true,
+                        changed_in_current_tick: true,
+                        moving_in_from: Some(direction.opposite()),
+                        falling,
+                        ..neighbor
+                    });
+                }
+            }
+        }
+    }
+
+    fn move_player(&mut self, input: Input) {
+        let direction = input.direction();
+        if direction.is_none() {
+            self.push_ticks = 0;
+        }
+        for y in 0..self.level.height {
+            for x in 0..self.level.width {
+                if let Some(Cell { object: Some(Object::Player { .. }), changed_in_current_tick: false, .. }) = self.level.cell(x, y) {
+                    match direction {
+                        None => self.set_player_action(x, y, PlayerAction::Idle),
+                        Some(direction) if input.fire => {
+                            self.set_player_action(x, y, PlayerAction::Digging);
+                            self.snap_if_possible(x, y, direction);
+                        }
+                        Some(direction) => {
+                            self.set_player_action(x, y, PlayerAction::Walking(direction));
+                            self.move_player_if_possible(x, y, direction);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Changes the animation of the player at (x, y).
+    fn set_player_action(&mut self, x: u16, y: u16, action: PlayerAction) {
+        if let Some(&cell) = self.level.cell(x, y) {
+            if let Some(Object::Player { frame_offset, .. }) = cell.object {
+                self.level.set_cell(x, y, Cell { object: Some(Object::Player { action, frame_offset }), ..cell });
+            }
+        }
+    }
+
+    fn move_player_if_possible(&mut self, x: u16, y: u16, direction: Direction) {
+        if let Some(&neighbor) = self.level.neighbor(x, y, direction) {
+            if !neighbor.object.is_some_and(|object| object.can_be_pushed()) {
+                self.push_ticks = 0;
+            }
+            if let Some(Object::Lock { color }) = neighbor.object {
+                if self.inventory.use_key(color) {
+                    self.events.push(GameEvent::LockOpened { color });
+                    self.move_object(x, y, direction, false);
+                }
+            } else if self.can_break(neighbor) {
+                self.break_with_pickaxe(x, y, neighbor);
+            } else if neighbor.object.is_some_and(|object| object.can_be_pushed()) {
+                self.push_if_possible(x, y, direction, neighbor);
+            } else if neighbor.can_be_entered() {
+                self.collect(neighbor.object);
+                self.move_object(x, y, direction, false);
+                if let Some(Object::Door { .. }) = neighbor.object {
+                    self.complete();
+                }
+            }
+        }
+    }
+
+    /// Objects can only be pushed horizontally and not while they are falling.
+    /// Heavy objects only move after the player has pushed against them for a
+    /// while.  The player follows the pushed object.
+    fn push_if_possible(&mut self, x: u16, y: u16, direction: Direction, target: Cell) {
+        if direction == Direction::North || direction == Direction::South || target.falling {
+            self.push_ticks = 0;
+            return;
+        }
+        if !self.level.neighbor(target.x, target.y, direction).is_some_and(|behind| behind.is_free()) {
+            self.push_ticks = 0;
+            return;
+        }
+        if self.push_ticks < target.object.map_or(0, Object::push_resistance) {
+            self.push_ticks += 1;
+            return;
+        }
+        self.push_ticks = 0;
+        self.move_object(target.x, target.y, direction, false);
+        self.move_object(x, y, direction, false);
+    }
+
+    /// Bricks and walls that are not at the border of the level can be broken
+    /// with a pickaxe.
+    fn can_break(&self, cell: Cell) -> bool {
+        self.inventory.pickaxe_uses > 0 && match cell.object {
+            Some(Object::Brick) => true,
+            Some(Object::Wall { .. }) => !self.level.is_border(cell.x, cell.y),
+            _ => false,
+        }
+    }
+
+    /// Breaking takes the player's move, the player stays where they are.
+    fn break_with_pickaxe(&mut self, x: u16, y: u16, target: Cell) {
+        if let (Some(&cell), Some(object)) = (self.level.cell(x, y), target.object) {
+            self.inventory.pickaxe_uses -= 1;
+            self.level.set_cell(x, y, Cell { changed_in_current_tick: true, ..cell });
+            self.set_player_action(x, y, PlayerAction::Digging);
+            self.level.set_cell(target.x, target.y, Cell {
+                object: None,
+                changed_in_current_tick: true,
+                ..target
+            });
+            self.level.update_neighbors_around(target.x, target.y);
+            self.events.push(GameEvent::Broken { object });
+        }
+    }
+
+    /// Clears the neighboring cell like entering it would do, but the player
+    /// stays where they are.
+    fn snap_if_possible(&mut self, x: u16, y: u16, direction: Direction) {
+        if let Some(&cell) = self.level.cell(x, y) {
+            if let Some((x_new, y_new)) = self.level.neighbor_position(x, y, direction) {
+                if let Some(&neighbor) = self.level.cell(x_new, y_new) {
+                    let is_door = matches!(neighbor.object, Some(Object::Door { .. }));
+                    if neighbor.object.is_some() && neighbor.can_be_entered() && !is_door {
+                        self.collect(neighbor.object);
+                        self.level.set_cell(x, y, Cell { changed_in_current_tick: true, ..cell });
+                        self.level.set_cell(x_new, y_new, Cell {
+                            object: None,
+                            changed_in_current_tick: true,
+                            ..neighbor
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Collects the object the player takes from a cell.
+    fn collect(&mut self, object: Option<Object>) {
+        match object {
+            Some(Object::Dirt { .. }) => self.events.push(GameEvent::Dug),
+            Some(Object::Gem { kind, .. }) => {
+                self.score += kind.points();
+                self.gems_collected += 1;
+                self.events.push(GameEvent::GemCollected { kind });
+            }
+            Some(Object::Key { color, .. }) => {
+                *self.inventory.keys_mut(color) += 1;
+                self.events.push(GameEvent::KeyCollected { color });
+            }
+            Some(Object::Pickaxe { .. }) => {
+                self.inventory.pickaxe_uses = self.inventory.pickaxe_uses.saturating_add(PICKAXE_USES);
+                self.events.push(GameEvent::PickaxeCollected);
+            }
+            _ => {}
+        }
+    }
+
+    /// Enemies follow the wall on their left-hand side: they turn left if
+    /// possible, otherwise they go straight, turn right or turn back.
+    fn move_enemies(&mut self) {
+        for y in 0..self.level.height {
+            for x in 0..self.level.width {
+                if let Some(&Cell {
+                    object: Some(Object::Enemy { facing, frame_offset }),
+                    changed_in_current_tick: false,
+                    ..
+                }) = self.level.cell(x, y) {
+                    let directions = [facing.turn_left(), facing, facing.turn_right(), facing.opposite()];
+                    for &direction in &directions {
+                        if self.level.neighbor(x, y, direction).is_some_and(|neighbor| neighbor.is_free()) {
+                            if let Some(&cell) = self.level.cell(x, y) {
+                                self.level.set_cell(x, y, Cell {
+                                    object: Some(Object::Enemy { facing: direction, frame_offset }),
+                                    ..cell
+                                });
+                            }
+                            self.move_object(x, y, direction, false);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Airships fly straight ahead and turn around when they hit an obstacle.
+    /// If they hit the player or an enemy, both explode.
+    fn move_airships(&mut self) {
+        for y in 0..self.level.height {
+            for x in 0..self.level.width {
+                if let Some(&cell) = self.level.cell(x, y) {
+                    let direction = match cell {
+                        Cell { object: Some(Object::AirshipLeft { .. }), changed_in_current_tick: false, .. } => {
+                            Direction::West
+                        }
+                        Cell { object: Some(Object::AirshipRight { .. }), changed_in_current_tick: false, .. } => {
+                            Direction::East
+                        }
+                        _ => continue,
+                    };
+                    match self.level.neighbor(x, y, direction) {
+                        Some(neighbor) if neighbor.is_free() => self.move_object(x, y, direction, false),
+                        Some(&Cell { x: x_hit, y: y_hit, object: Some(object), .. }) if object.can_be_crushed() => {
+                            self.kill(x_hit, y_hit);
+                            self.level.set_cell(x, y, Cell { object: None, changed_in_current_tick: true, ..cell });
+                            self.events.push(GameEvent::Exploded);
+                        }
+                        _ => self.level.set_cell(x, y, Cell {
+                            object: cell.object.map(Object::turned_around),
+                            changed_in_current_tick: true,
+                            ..cell
+                        }),
+                    }
+                }
+            }
+        }
+    }
+
+    /// The player dies as soon as an enemy is next to them.
+    fn check_enemy_contact(&mut self) {
+        for y in 0..self.level.height {
+            for x in 0..self.level.width {
+                if let Some(Cell { object: Some(Object::Player { .. }), .. }) = self.level.cell(x, y) {
+                    let caught = [Direction::North, Direction::East, Direction::South, Direction::West].iter()
+                        .any(|&direction| matches!(
+                            self.level.neighbor(x, y, direction),
+                            Some(Cell { object: Some(Object::Enemy { .. }), .. })
+                        ));
+                    if caught {
+                        self.kill(x, y);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Boulders and gems fall down into free cells and roll off rounded
+    /// objects.  A falling object crushes the player or an enemy it lands on.
+    /// Balloons rise up into free cells.
+    fn apply_gravity(&mut self) {
+        for y in 0..self.level.height {
+            for x in 0..self.level.width {
+                if let Some(&cell) = self.level.cell(x, y) {
+                    if let Cell { object: Some(object), changed_in_current_tick: false, .. } = cell {
+                        if object.falls() {
+                            self.apply_gravity_to_cell(x, y, cell, object);
+                        } else if object.rises()
+                            && self.level.neighbor(x, y, Direction::North).is_some_and(|above| above.is_free()) {
+                            self.move_object(x, y, Direction::North, false);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply_gravity_to_cell(&mut self, x: u16, y: u16, cell: Cell, object: Object) {
+        let below = match self.level.neighbor(x, y, Direction::South) {
+            Some(&below) => below,
+            None => return,
+        };
+        if below.is_free() {
+            self.move_object(x, y, Direction::South, true);
+            return;
+        }
+        match below.object {
+            Some(below_object) if cell.falling && below_object.can_be_crushed() => {
+                self.kill(below.x, below.y);
+                self.move_object(x, y, Direction::South, true);
+                return;
+            }
+            Some(below_object) if below_object.is_rounded() && below.moving_in_from.is_none() => {
+                for &direction in &[Direction::West, Direction::East] {
+                    if self.can_roll(x, y, direction) {
+                        self.move_object(x, y, direction, false);
+                        return;
+                    }
+                }
+            }
+            _ => {}
+        }
+        if cell.falling {
+            self.level.set_cell(x, y, Cell { falling: false, ..cell });
+            self.events.push(GameEvent::Landed { object });
+        }
+    }
+
+    /// An object can roll to the side if both the cell next to it and the cell
+    /// below that are free.
+    fn can_roll(&self, x: u16, y: u16, direction: Direction) -> bool {
+        match self.level.neighbor_position(x, y, direction) {
+            Some((x_side, y_side)) => {
+                self.level.cell(x_side, y_side).is_some_and(|side| side.is_free())
+                    && self.level.neighbor(x_side, y_side, Direction::South).is_some_and(|below| below.is_free())
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the player or an enemy from the level.
+    fn kill(&mut self, x: u16, y: u16) {
+        if let Some(&cell) = self.level.cell(x, y) {
+            self.level.set_cell(x, y, Cell { object: None, ..cell });
+            match cell.object {
+                Some(Object::Player { .. }) => {
+                    self.lives = self.lives.saturating_sub(1);
+                    self.status = Status::Dying { x, y, ticks_left: DEATH_ANIMATION_TICKS };
+                    self.events.push(GameEvent::PlayerDied);
+                }
+                Some(Object::Enemy { .. }) => self.events.push(GameEvent::EnemyKilled),
+                _ => {}
+            }
+        }
+    }
+
+    fn reset_changed_in_current_tick(&mut self) {
+        for y in 0..self.level.height {
+            for x in 0..self.level.width {
+                if let Some(&cell) = self.level.cell(x, y) {
+                    self.level.set_cell(x, y, Cell { changed_in_current_tick: false, ..cell })
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a level from rows of characters: `#` is a wall, `=` is a brick,
+    /// `.` is dirt, `o` is a boulder, `*` is a gem, `k` is a yellow key, `L` is
+    /// a yellow lock, `D` is a door, `x` is a pickaxe, `b` is a
+    /// balloon, `<` and `>` are airships, `P` is the player, `E` is an enemy and a
+    /// space is empty.
+    pub fn level_from_rows(rows: &[&str]) -> Level {
+        let width = rows[0].len() as u16;
+        let height = rows.len() as u16;
+        let mut map = vec![];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let object = match c {
+                    '#' => Some(Object::Wall { north: false, east: false, south: false, west: false, generic: false }),
+                    '.' => Some(Object::Dirt { north: false, east: false, south: false, west: false, generic: false }),
+                    '=' => Some(Object::Brick),
+                    'o' => Some(Object::Boulder),
+                    '*' => Some(Object::Gem { kind: GemKind::Gem1, frame_offset: 0 }),
+                    'k' => Some(Object::Key { color: KeyColor::Yellow, frame_offset: 0 }),
+                    'L' => Some(Object::Lock { color: KeyColor::Yellow }),
+                    'D' => Some(Object::Door { open: false, frame_offset: 0 }),
+                    'x' => Some(Object::Pickaxe { frame_offset: 0 }),
+                    'b' => Some(Object::Balloon),
+                    '<' => Some(Object::AirshipLeft { frame_offset: 0 }),
+                    '>' => Some(Object::AirshipRight { frame_offset: 0 }),
+                    'P' => Some(Object::Player { action: PlayerAction::Idle, frame_offset: 0 }),
+                    'E' => Some(Object::Enemy { facing: Direction::West, frame_offset: 0 }),
+                    ' ' => None,
+                    _ => panic!("unknown object {:?}", c),
+                };
+                map.push(Cell::new(x as u16, y as u16, object));
+            }
+        }
+        Level { width, height, map }
+    }
+
+    fn object_at(game: &Game, x: u16, y: u16) -> Option<Object> {
+        game.level.cell(x, y).unwrap().object
+    }
+
+    const NONE: Input = Input { left: false, right: false, up: false, down: false, fire: false };
+    const RIGHT: Input = Input { left: false, right: true, up: false, down: false, fire: false };
+
+    #[test]
+    fn test_player_moves_every_other_tick() {
+        let mut game = Game::new(level_from_rows(&[
+            "#####",
+            "#P. #",
+            "#####",
+        ]));
+        game.step(RIGHT);
+        assert_eq!(game.player_position(), Some((2, 1)));
+        assert_eq!(game.level.cell(2, 1).unwrap().moving_in_from, Some(Direction::West));
+        assert_eq!(game.level.cell(1, 1).unwrap().object, None);
+
+        // the movement is finished in the next tick
+        game.step(RIGHT);
+        assert_eq!(game.player_position(), Some((2, 1)));
+        assert_eq!(game.level.cell(2, 1).unwrap().moving_in_from, None);
+
+        game.step(RIGHT);
+        assert_eq!(game.player_position(), Some((3, 1)));
+
+        // walls cannot be entered
+        game.step(RIGHT);
+        game.step(RIGHT);
+        assert_eq!(game.player_position(), Some((3, 1)));
+    }
+
+    #[test]
+    fn test_snap() {
+        let mut game = Game::new(level_from_rows(&[
+            "####",
+            "#P.#",
+            "####",
+        ]));
+        game.step(Input { fire: true, ..RIGHT });
+        assert_eq!(game.player_position(), Some((1, 1)));
+        assert_eq!(game.level.cell(2, 1).unwrap().object, None);
+    }
+
+    #[test]
+    fn test_player_action() {
+        let mut game = Game::new(level_from_rows(&[
+            "#####",
+            "#P .#",
+            "#####",
+        ]));
+        let action = |game: &Game| match game.player_position().and_then(|(x, y)| object_at(game, x, y)) {
+            Some(Object::Player { action, .. }) => action,
+            object => panic!("no player: {:?}", object),
+        };
+        game.step(RIGHT);
+        assert_eq!(action(&game), PlayerAction::Walking(Direction::East));
+        game.step(NONE);
+        game.step(NONE);
+        assert_eq!(action(&game), PlayerAction::Idle);
+        game.step(Input { fire: true, ..RIGHT });
+        assert_eq!(action(&game), PlayerAction::Digging);
+    }
+
+    #[test]
+    fn test_update_neighbors() {
+        let game = Game::new(level_from_rows(&[
+            "###",
+            "#..",
+            "#P ",
+        ]));
+        assert_eq!(
+            game.level.cell(1, 1).unwrap().object,
+            Some(Object::Dirt { north: false, east: false, south: true, west: false, generic: false }),
+        );
+        assert_eq!(
+            game.level.cell(0, 1).unwrap().object,
+            Some(Object::Wall { north: false, east: true, south: false, west: false, generic: false }),
+        );
+    }
+
+    #[test]
+    fn test_boulder_falls_and_lands() {
+        let mut game = Game::new(level_from_rows(&[
+            "###",
+            "#o#",
+            "# #",
+            "# #",
+            "###",
+        ]));
+        game.step(NONE);
+        assert_eq!(object_at(&game, 1, 2), Some(Object::Boulder));
+        assert!(game.level.cell(1, 1).unwrap().post_occupied);
+        game.step(NONE);
+        assert_eq!(object_at(&game, 1, 2), Some(Object::Boulder));
+        assert!(game.level.cell(1, 1).unwrap().is_free());
+        game.step(NONE);
+        assert_eq!(object_at(&game, 1, 3), Some(Object::Boulder));
+        game.step(NONE);
+        assert!(game.events.is_empty());
+        game.step(NONE);
+        assert_eq!(object_at(&game, 1, 3), Some(Object::Boulder));
+        assert_eq!(game.events, vec![GameEvent::Landed { object: Object::Boulder }]);
+        assert!(!game.level.cell(1, 3).unwrap().falling);
+    }
+
+    #[test]
+    fn test_boulder_rolls_off_rounded_objects() {
+        let mut game = Game::new(level_from_rows(&[
+            "#####",
+            "# o #",
+            "# * #",
+            "#####",
+        ]));
+        game.step(NONE);
+        assert_eq!(object_at(&game, 1, 1), Some(Object::Boulder));
+        game.step(NONE);
+        game.step(NONE);
+        assert_eq!(object_at(&game, 1, 2), Some(Object::Boulder));
+
+        // walls are not rounded
+        let mut game = Game::new(level_from_rows(&[
+            "#####",
+            "# o #",
+            "#####",
+        ]));
+        game.step(NONE);
+        assert_eq!(object_at(&game, 2, 1), Some(Object::Boulder));
+    }
+
+    #[test]
+    fn test_falling_boulder_crushes() {
+        let mut game = Game::new(level_from_rows(&[
+            "###",
+            "#o#",
+            "# #",
+            "#P#",
+            "###",
+        ]));
+        game.step(NONE);
+        game.step(NONE);
+        game.step(NONE);
+        assert_eq!(object_at(&game, 1, 3), Some(Object::Boulder));
+        assert_eq!(game.events, vec![GameEvent::PlayerDied]);
+        assert_eq!(game.player_position(), None);
+        assert!(matches!(game.status, Status::Dying { .. }));
+
+        let mut game = Game::new(level_from_rows(&[
+            "#####",
+            "##o##",
+            "##E##",
+            "#####",
+        ]));
+        let cell = *game.level.cell(2, 1).unwrap();
+        game.level.set_cell(2, 1, Cell { falling: true, ..cell });
+        game.step(NONE);
+        assert_eq!(object_at(&game, 2, 2), Some(Object::Boulder));
+        assert_eq!(game.events, vec![GameEvent::EnemyKilled]);
+        assert_eq!(game.status, Status::Running);
+    }
+
+    #[test]
+    fn test_resting_boulder_does_not_crush() {
+        let mut game = Game::new(level_from_rows(&[
+            "###",
+            "#o#",
+            "#.#",
+            "#P#",
+            "###",
+        ]));
+        game.step(Input { up: true, ..NONE });
+        assert_eq!(game.player_position(), Some((1, 2)));
+        assert_eq!(game.events, vec![GameEvent::Dug]);
+        for _ in 0..10 {
+            game.step(NONE);
+        }
+        assert_eq!(game.player_position(), Some((1, 2)));
+        assert!(game.events.is_empty());
+    }
+
+    #[test]
+    fn test_collect_gems_and_complete_level() {
+        let mut game = Game::new(level_from_rows(&[
+            "######",
+            "#P* *#",
+            "######",
+        ]));
+        assert_eq!(game.gems_total, 2);
+        game.step(RIGHT);
+        assert_eq!(game.events, vec![GameEvent::GemCollected { kind: GemKind::Gem1 }]);
+        assert_eq!(game.score, 10);
+        assert_eq!(game.gems_remaining(), 1);
+        assert_eq!(game.status, Status::Running);
+        game.step(RIGHT);
+        game.step(RIGHT);
+        game.step(RIGHT);
+        game.step(Input { fire: true, ..RIGHT });
+        assert_eq!(game.player_position(), Some((3, 1)));
+        assert_eq!(game.events, vec![
+            GameEvent::GemCollected { kind: GemKind::Gem1 },
+            GameEvent::LevelCompleted,
+        ]);
+        assert_eq!(game.score, 20);
+        assert_eq!(game.status, Status::Completed);
+
+        // the level has ended
+        game.step(Input { left: true, ..NONE });
+        assert_eq!(game.player_position(), Some((3, 1)));
+        assert!(game.events.is_empty());
+    }
+
+    #[test]
+    fn test_keys_locks_and_doors() {
+        let mut game = Game::new(level_from_rows(&[
+            "########",
+            "#PL*k D#",
+            "########",
+        ]));
+        assert!(!game.doors_open);
+
+        // the lock cannot be opened without a key
+        game.step(RIGHT);
+        assert_eq!(game.player_position(), Some((1, 1)));
+
+        let mut game = Game::new(level_from_rows(&[
+            "########",
+            "#Pk*L D#",
+            "########",
+        ]));
+        for _ in 0..2 {
+            game.step(RIGHT);
+            game.step(NONE);
+        }
+        assert_eq!(game.inventory.keys(KeyColor::Yellow), 1);
+        assert_eq!(game.player_position(), Some((3, 1)));
+        assert_eq!(game.status, Status::Running);
+        assert!(game.doors_open);
+        assert_eq!(object_at(&game, 6, 1), Some(Object::Door { open: true, frame_offset: 0 }));
+
+        game.step(RIGHT);
+        assert_eq!(game.events, vec![GameEvent::LockOpened { color: KeyColor::Yellow }]);
+        assert_eq!(game.inventory.yellow_keys, 0);
+        assert_eq!(game.player_position(), Some((4, 1)));
+        for _ in 0..2 {
+            game.step(NONE);
+            game.step(RIGHT);
+        }
+        assert_eq!(game.player_position(), Some((6, 1)));
+        assert_eq!(game.status, Status::Completed);
+        assert_eq!(game.events, vec![GameEvent::LevelCompleted]);
+
+        // the level time stops once the level has ended
+        assert_eq!(game.ticks, 9);
+        game.step(NONE);
+        assert_eq!(game.ticks, 9);
+    }
+
+    #[test]
+    fn test_pickaxe() {
+        let mut game = Game::new(level_from_rows(&[
+            "#######",
+            "#Px==##",
+            "#######",
+        ]));
+
+        game.step(RIGHT);
+        assert_eq!(game.events, vec![GameEvent::PickaxeCollected]);
+        assert_eq!(game.inventory.pickaxe_uses, PICKAXE_USES);
+        game.step(NONE);
+
+        // breaking takes a move, the player does not enter the cell
+        game.step(RIGHT);
+        assert_eq!(game.events, vec![GameEvent::Broken { object: Object::Brick }]);
+        assert_eq!(game.player_position(), Some((2, 1)));
+        assert_eq!(object_at(&game, 3, 1), None);
+        game.step(RIGHT);
+        game.step(NONE);
+        game.step(RIGHT);
+        game.step(RIGHT);
+        game.step(NONE);
+        game.step(RIGHT);
+        assert_eq!(game.events, vec![GameEvent::Broken { object: Object::Wall {
+            north: false, east: false, south: false, west: true, generic: false,
+        } }]);
+        assert_eq!(game.inventory.pickaxe_uses, 0);
+        assert_eq!(object_at(&game, 5, 1), None);
+
+        // the borders of the neighbors are updated
+        assert_eq!(
+            object_at(&game, 6, 1),
+            Some(Object::Wall { north: false, east: false, south: false, west: true, generic: false }),
+        );
+        assert_eq!(
+            object_at(&game, 5, 0),
+            Some(Object::Wall { north: false, east: false, south: true, west: false, generic: false }),
+        );
+
+        // walls at the border cannot be broken
+        game.step(NONE);
+        game.step(RIGHT);
+        game.step(NONE);
+        game.inventory.pickaxe_uses = 1;
+        game.step(RIGHT);
+        assert_eq!(game.player_position(), Some((5, 1)));
+        assert!(game.events.is_empty());
+        assert_eq!(game.inventory.pickaxe_uses, 1);
+    }
+
+    #[test]
+    fn test_enemy_follows_wall() {
+        let mut game = Game::new(level_from_rows(&[
+            "#####",
+            "#   #",
+            "# # #",
+            "#E  #",
+            "#####",
+        ]));
+        let mut positions = vec![];
+        for _ in 0..8 {
+            game.step(NONE);
+            game.step(NONE);
+            let enemy = game.level.map.iter()
+                .find(|cell| matches!(cell.object, Some(Object::Enemy { .. })))
+                .unwrap();
+            positions.push((enemy.x, enemy.y));
+        }
+        // facing west with the wall on its left, the enemy walks around the
+        // center pillar clockwise
+        assert_eq!(positions, vec![(1, 2), (1, 1), (2, 1), (3, 1), (3, 2), (3, 3), (2, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn test_enemy_catches_player() {
+        let mut game = Game::new(level_from_rows(&[
+            "######",
+            "#P  E#",
+            "######",
+        ]));
+        game.step(NONE);
+        game.step(NONE);
+        assert_eq!(game.status, Status::Running);
+        game.step(NONE);
+        assert_eq!(game.events, vec![GameEvent::PlayerDied]);
+        assert!(matches!(game.status, Status::Dying { .. }));
+        assert_eq!(game.player_position(), None);
+    }
+
+    #[test]
+    fn test_balloon_rises_and_can_be_pushed() {
+        let mut game = Game::new(level_from_rows(&[
+            "#####",
+            "#   #",
+            "#Pb #",
+            "#####",
+        ]));
+        game.step(NONE);
+        assert_eq!(object_at(&game, 2, 1), Some(Object::Balloon));
+        game.step(NONE);
+        game.step(Input { up: true, ..NONE });
+        game.step(NONE);
+        game.step(RIGHT);
+        assert_eq!(game.player_position(), Some((2, 1)));
+        assert_eq!(object_at(&game, 3, 1), Some(Object::Balloon));
+
+        // there is no space behind the balloon anymore
+        game.step(NONE);
+        game.step(RIGHT);
+        assert_eq!(game.player_position(), Some((2, 1)));
+        assert_eq!(object_at(&game, 3, 1), Some(Object::Balloon));
+    }
+
+    #[test]
+    fn test_airships() {
+        let mut game = Game::new(level_from_rows(&[
+            "#####",
+            "# < #",
+            "#####",
+        ]));
+        game.step(NONE);
+        assert_eq!(object_at(&game, 1, 1), Some(Object::AirshipLeft { frame_offset: 0 }));
+        game.step(NONE);
+        game.step(NONE);
+        assert_eq!(object_at(&game, 1, 1), Some(Object::AirshipRight { frame_offset: 0 }));
+        game.step(NONE);
+        assert_eq!(object_at(&game, 2, 1), Some(Object::AirshipRight { frame_offset: 0 }));
+
+        let mut game = Game::new(level_from_rows(&[
+            "#####",
+            "#P <#",
+            "#####",
+        ]));
+        game.step(NONE);
+        game.step(NONE);
+        game.step(NONE);
+        assert_eq!(game.events, vec![GameEvent::PlayerDied, GameEvent::Exploded]);
+        assert_eq!(object_at(&game, 1, 1), None);
+        assert_eq!(object_at(&game, 2, 1), None);
+        assert!(matches!(game.status, Status::Dying { .. }));
+    }
+
+    #[test]
+    fn test_push_boulder() {
+        let mut game = Game::new(level_from_rows(&[
+            "######",
+            "#Po  #",
+            "######",
+        ]));
+        game.step(RIGHT);
+        game.step(RIGHT);
+        assert_eq!(object_at(&game, 2, 1), Some(Object::Boulder));
+        game.step(RIGHT);
+        assert_eq!(game.player_position(), Some((2, 1)));
+        assert_eq!(object_at(&game, 3, 1), Some(Object::Boulder));
+        assert_eq!(game.level.cell(3, 1).unwrap().moving_in_from, Some(Direction::West));
+
+        // the resistance has to be overcome again
+        game.step(RIGHT);
+        game.step(RIGHT);
+        game.step(NONE);
+        game.step(RIGHT);
+        game.step(RIGHT);
+        assert_eq!(object_at(&game, 3, 1), Some(Object::Boulder));
+        game.step(RIGHT);
+        assert_eq!(object_at(&game, 4, 1), Some(Object::Boulder));
+
+        // there is no space behind the boulder anymore
+        for _ in 0..6 {
+            game.step(RIGHT);
+        }
+        assert_eq!(game.player_position(), Some((3, 1)));
+        assert_eq!(object_at(&game, 4, 1), Some(Object::Boulder));
+    }
+
+    #[test]
+    fn test_lives() {
+        let rows = [
+            "#####",
+            "#P <#",
+            "#####",
+        ];
+        let mut game = Game::new(level_from_rows(&rows));
+        assert_eq!(game.lives, INITIAL_LIVES);
+        for lives in (0..INITIAL_LIVES).rev() {
+            game.step(NONE);
+            game.step(NONE);
+            game.step(NONE);
+            assert_eq!(game.status, Status::Dying { x: 1, y: 1, ticks_left: DEATH_ANIMATION_TICKS });
+            assert_eq!(game.lives, lives);
+            for _ in 0..DEATH_ANIMATION_TICKS {
+                game.step(NONE);
+            }
+            assert!(matches!(game.status, Status::Dying { ticks_left: 0, .. }));
+            game.step(NONE);
+            if lives > 0 {
+                assert_eq!(game.status, Status::Died);
+                game.start_level(level_from_rows(&rows));
+                assert_eq!(game.status, Status::Running);
+                assert_eq!(game.lives, lives);
+            } else {
+                assert_eq!(game.status, Status::GameOver);
+            }
+        }
+    }
+}
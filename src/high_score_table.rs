@@ -1,13 +1,86 @@
-use std::fs::File;
-use std::io::Read;
+//! Reading and writing the high score table (`high.dat`) of an episode.
+
+use std::fs;
 use std::path::Path;
 
+use crate::error::Error;
+
+/// The number of entries of a newly created high score table file.  Loaded
+/// tables keep the number of entries of their file.
+pub const DEFAULT_CAPACITY: usize = 10;
+
+/// An entry of the high score table.
 #[derive(Debug)]
 pub struct HighScore {
     name: String,
     score: u32,
 }
 
+impl HighScore {
+    /// Names consist of exactly three letters from A to Z.
+    pub fn new(name: &str, score: u32) -> Result<HighScore, Error> {
+        if name.len() != 3 || !name.bytes().all(|c| c.is_ascii_uppercase()) {
+            return Err(Error::InvalidName { name: name.into() });
+        }
+        Ok(HighScore { name: name.into(), score })
+    }
+
+    /// The three letters of the name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The points of the entry.
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+}
+
+
+/// The high scores in descending order, at most `capacity` of them.
+#[derive(Debug)]
+pub struct HighScoreTable {
+    /// The entries, the highest score first.
+    pub high_scores: Vec<HighScore>,
+    capacity: usize,
+}
+
+impl HighScoreTable {
+    /// An empty table with room for `capacity` entries.
+    pub fn new(capacity: usize) -> HighScoreTable {
+        HighScoreTable { high_scores: vec![], capacity }
+    }
+
+    /// The number of entries of the file, including empty ones.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns whether the score is high enough to be inserted into the table.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0 && (
+            self.high_scores.len() < self.capacity
+            || self.high_scores.iter().any(|high_score| high_score.score < score)
+        )
+    }
+
+    /// Inserts the high score behind all entries with a higher or equal score
+    /// and drops the lowest entries that do not fit into the table anymore.
+    /// Returns the position of the new entry or `None` if the score is not high
+    /// enough.
+    pub fn insert(&mut self, high_score: HighScore) -> Option<usize> {
+        if !self.qualifies(high_score.score) {
+            return None;
+        }
+        let position = self.high_scores.iter()
+            .position(|other| other.score < high_score.score)
+            .unwrap_or(self.high_scores.len());
+        self.high_scores.insert(position, high_score);
+        self.high_scores.truncate(self.capacity);
+        Some(position)
+    }
+}
+
 
 /**
  * High score table files contain multiple high score entries. The format of an
@@ -16,18 +89,17 @@ pub struct HighScore {
  * - padding (1 byte, should be 0)
  * - score (4 bytes, little endian)
  */
-fn parse(buffer: &[u8]) -> Result<Vec<HighScore>, String> {
+fn parse(path: &Path, buffer: &[u8]) -> Result<HighScoreTable, Error> {
     let mut high_scores = vec!();
     let mut iter = buffer.chunks_exact(8);
-    for chunk in iter.by_ref() {
+    // Entries behind the first empty one are ignored, but still count towards
+    // the capacity of the table.
+    for (i, chunk) in iter.by_ref().enumerate() {
         let name_buffer = &chunk[..3];
         if !name_buffer.iter().all(|c| *c < 26) {
-            return Err(String::from("name contains invalid characters"));
+            return Err(Error::InvalidHighScoreName { path: path.into(), offset: i * 8 });
         }
-        let name = match String::from_utf8(name_buffer.iter().map(|c| c + 65).collect()) {
-            Ok(name) => name,
-            Err(_) => return Err(String::from("cannot parse name")),
-        };
+        let name = name_buffer.iter().map(|&c| (c + 65) as char).collect();
         let score = chunk[4..].iter().enumerate().map(|(i, &c)| (c as u32) << (i * 8) as u32).sum::<u32>();
         if score == 0 {
             break;
@@ -35,23 +107,41 @@ fn parse(buffer: &[u8]) -> Result<Vec<HighScore>, String> {
         high_scores.push(HighScore { name, score });
     }
     let remainder = iter.remainder();
-    if remainder.len() > 0 {
-        return Err(String::from("unparsed bytes at end"));
+    if !remainder.is_empty() {
+        let offset = buffer.len() - remainder.len();
+        return Err(Error::SizeMismatch { path: path.into(), offset, expected: 8, found: remainder.len() });
     }
-    Ok(high_scores)
+    Ok(HighScoreTable { high_scores, capacity: buffer.len() / 8 })
 }
 
 
-pub fn load(path: &Path) -> Result<Vec<HighScore>, String> {
-    let mut file = match File::open(path) {
-        Ok(file) => file,
-        Err(reason) => return Err(format!("cannot open {}: {:?}", path.display(), reason)),
-    };
+/// Serializes the high scores to the format described at `parse`.  The table
+/// is filled up with empty entries to its capacity.
+fn serialize(table: &HighScoreTable) -> Vec<u8> {
     let mut buffer = vec!();
-    if let Err(reason) = file.read_to_end(&mut buffer) {
-        return Err(format!("cannot read {}: {:?}", path.display(), reason));
+    for high_score in table.high_scores.iter().take(table.capacity) {
+        buffer.extend(high_score.name.bytes().map(|c| c - 65));
+        buffer.push(0);
+        buffer.extend_from_slice(&high_score.score.to_le_bytes());
+    }
+    buffer.resize(table.capacity * 8, 0);
+    buffer
+}
+
+
+/// Reads the high score table file.
+pub fn load(path: &Path) -> Result<HighScoreTable, Error> {
+    match fs::read(path) {
+        Ok(buffer) => parse(path, &buffer),
+        Err(reason) => Err(Error::io(path, reason)),
     }
-    parse(&buffer)
+}
+
+
+/// Writes the table with all its entries, padding the file with empty
+/// entries up to its capacity.
+pub fn save(path: &Path, table: &HighScoreTable) -> Result<(), Error> {
+    fs::write(path, serialize(table)).map_err(|reason| Error::io(path, reason))
 }
 
 
@@ -70,18 +160,26 @@ mod test {
             0, 1, 2, 0, 0, 0, 0, 0,
             0, 1, 2, 0, 0, 0, 0, 0,
         ];
-        let high_scores = parse(&buffer).unwrap();
-        assert_eq!(high_scores.len(), 1);
-        assert_eq!(high_scores[0].name, "JAM");
-        assert_eq!(high_scores[0].score, 1113);
+        let table = parse(Path::new("high.dat"), &buffer).unwrap();
+        assert_eq!(table.capacity(), 3);
+        assert_eq!(table.high_scores.len(), 1);
+        assert_eq!(table.high_scores[0].name, "JAM");
+        assert_eq!(table.high_scores[0].score, 1113);
 
         let buffer = [
             9, 0, 12, 0, 89, 4, 0, 0,
             0, 1, 2, 0, 0, 0, 0, 0,
             0, 1, 2, 0,
         ];
-        let error = parse(&buffer).unwrap_err();
-        assert_eq!(error, "unparsed bytes at end");
+        let error = parse(Path::new("high.dat"), &buffer).unwrap_err();
+        assert!(matches!(error, Error::SizeMismatch { offset: 16, expected: 8, found: 4, .. }));
+
+        let buffer = [
+            9, 0, 12, 0, 89, 4, 0, 0,
+            0, 26, 2, 0, 1, 0, 0, 0,
+        ];
+        let error = parse(Path::new("high.dat"), &buffer).unwrap_err();
+        assert!(matches!(error, Error::InvalidHighScoreName { offset: 8, .. }));
     }
 
     #[test]
@@ -93,9 +191,80 @@ mod test {
             0, 1, 2, 0, 0, 0, 0, 0,
         ]).unwrap();
         let path = file.into_temp_path();
-        let high_scores = load(&path).unwrap();
-        assert_eq!(high_scores.len(), 1);
-        assert_eq!(high_scores[0].name, "JAM");
-        assert_eq!(high_scores[0].score, 1113);
+        let table = load(&path).unwrap();
+        assert_eq!(table.capacity(), 3);
+        assert_eq!(table.high_scores.len(), 1);
+        assert_eq!(table.high_scores[0].name, "JAM");
+        assert_eq!(table.high_scores[0].score, 1113);
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(HighScore::new("JAM", 5).unwrap().name(), "JAM");
+        assert_eq!(
+            HighScore::new("Jam", 5).unwrap_err().to_string(),
+            "invalid name \"Jam\", expected three letters from A to Z",
+        );
+        assert!(HighScore::new("JAMES", 5).is_err());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut table = HighScoreTable::new(4);
+        table.high_scores = vec![HighScore::new("JAM", 1113).unwrap(), HighScore::new("ABC", 70000).unwrap()];
+        let buffer = serialize(&table);
+        assert_eq!(buffer.len(), 4 * 8);
+        assert_eq!(&buffer[..16], &[
+            9, 0, 12, 0, 89, 4, 0, 0,
+            0, 1, 2, 0, 112, 17, 1, 0,
+        ]);
+        assert!(buffer[16..].iter().all(|&c| c == 0));
+
+        let parsed = parse(Path::new("high.dat"), &buffer).unwrap();
+        assert_eq!(parsed.capacity(), 4);
+        assert_eq!(parsed.high_scores.len(), 2);
+        assert_eq!(parsed.high_scores[1].name, "ABC");
+        assert_eq!(parsed.high_scores[1].score, 70000);
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut table = HighScoreTable::new(DEFAULT_CAPACITY);
+        assert_eq!(table.insert(HighScore::new("AAA", 0).unwrap()), None);
+        for i in 0..DEFAULT_CAPACITY as u32 {
+            assert_eq!(table.insert(HighScore::new("AAA", 100 * (i + 1)).unwrap()), Some(0));
+        }
+        assert_eq!(table.insert(HighScore::new("BBB", 100).unwrap()), None);
+        assert_eq!(table.insert(HighScore::new("CCC", 550).unwrap()), Some(5));
+        assert_eq!(table.high_scores.len(), DEFAULT_CAPACITY);
+        assert_eq!(table.high_scores[4].score, 600);
+        assert_eq!(table.high_scores[5].name, "CCC");
+        assert_eq!(table.high_scores[6].score, 500);
+        assert_eq!(table.high_scores[DEFAULT_CAPACITY - 1].score, 200);
+
+        // equal scores are inserted behind existing ones
+        assert_eq!(table.insert(HighScore::new("DDD", 1000).unwrap()), Some(1));
+
+        // the table keeps the capacity of its file
+        let mut table = HighScoreTable::new(2);
+        for &score in &[300, 100, 200] {
+            table.insert(HighScore::new("AAA", score).unwrap());
+        }
+        assert_eq!(table.high_scores.iter().map(HighScore::score).collect::<Vec<_>>(), vec![300, 200]);
+        assert!(!table.qualifies(150));
+    }
+
+    #[test]
+    fn test_save() {
+        let path = NamedTempFile::new().unwrap().into_temp_path();
+        let mut table = HighScoreTable::new(15);
+        table.insert(HighScore::new("JAM", 1113).unwrap());
+        save(&path, &table).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 15 * 8);
+        let table = load(&path).unwrap();
+        assert_eq!(table.capacity(), 15);
+        assert_eq!(table.high_scores.len(), 1);
+        assert_eq!(table.high_scores[0].name, "JAM");
+        assert_eq!(table.high_scores[0].score, 1113);
     }
 }
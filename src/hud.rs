@@ -0,0 +1,70 @@
+//! The heads-up display, a row of letter and object tiles above the level.
+
+use crate::game::Game;
+use crate::interpolation::TICKS_PER_SECOND;
+use crate::objects::{self, GemKind, KeyColor, Object, PlayerAction};
+
+/// The number of rows of tiles the HUD takes from the top of the window.
+pub const HUD_ROWS: u16 = 1;
+
+/// Returns the tile numbers of the HUD from left to right, `None` leaves a
+/// column empty.  The HUD shows the score, the remaining gems, the keys of
+/// every color, the pickaxe uses, the lives and the seconds spent in the
+/// level.  Numbers that do not fit are capped, e.g., at 99.
+pub fn hud_tiles(game: &Game, score: u32) -> Vec<Option<u8>> {
+    let icon = |object: Object| Some(object.tile_number(0));
+    let mut tiles = vec![];
+    push_number(&mut tiles, score, 6);
+    tiles.push(None);
+    tiles.push(icon(Object::Gem { kind: GemKind::Gem1, frame_offset: 0 }));
+    push_number(&mut tiles, game.gems_remaining(), 2);
+    tiles.push(None);
+    for &color in &[KeyColor::Yellow, KeyColor::Red, KeyColor::Grey] {
+        tiles.push(icon(Object::Key { color, frame_offset: 0 }));
+        push_number(&mut tiles, game.inventory.keys(color) as u32, 1);
+    }
+    tiles.push(None);
+    tiles.push(icon(Object::Pickaxe { frame_offset: 0 }));
+    push_number(&mut tiles, game.inventory.pickaxe_uses as u32, 2);
+    tiles.push(None);
+    tiles.push(icon(Object::Player { action: PlayerAction::Idle, frame_offset: 0 }));
+    push_number(&mut tiles, game.lives as u32, 1);
+    tiles.push(None);
+    push_number(&mut tiles, game.ticks / TICKS_PER_SECOND, 3);
+    tiles
+}
+
+
+/// Adds the number with leading zeros as `digits` digit tiles.
+fn push_number(tiles: &mut Vec<Option<u8>>, number: u32, digits: u32) {
+    let number = number.min(10u32.pow(digits) - 1);
+    let text = format!("{:0width$}", number, width = digits as usize);
+    tiles.extend(text.chars().map(objects::letter_tile_number));
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::level::{Cell, Level};
+
+    use super::*;
+
+    #[test]
+    fn test_hud_tiles() {
+        let mut level = Level::new(3, 1);
+        level.set_cell(0, 0, Cell::new(0, 0, Some(Object::Player { action: PlayerAction::Idle, frame_offset: 0 })));
+        level.set_cell(2, 0, Cell::new(2, 0, Some(Object::Gem { kind: GemKind::Gem2, frame_offset: 0 })));
+        let mut game = Game::new(level);
+        game.inventory.red_keys = 12;
+        game.ticks = 20 * TICKS_PER_SECOND + 1;
+
+        let digit = objects::letter_tile_number;
+        let tiles = hud_tiles(&game, 1_234_567);
+        assert_eq!(tiles.len(), 28);
+        assert_eq!(&tiles[..6], &[digit('9'); 6]);
+        assert_eq!(&tiles[6..10], &[None, Some(64), digit('0'), digit('1')]);
+        assert_eq!(&tiles[11..17], &[Some(40), digit('0'), Some(46), digit('9'), Some(52), digit('0')]);
+        assert_eq!(&tiles[22..24], &[Some(156), digit('3')]);
+        assert_eq!(&tiles[25..], &[digit('0'), digit('2'), digit('0')]);
+    }
+}
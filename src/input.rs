@@ -0,0 +1,83 @@
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::{EventPump, GameControllerSubsystem};
+
+use cm_rust::controls::{Binding, Controls, ACTIONS};
+use cm_rust::game::Input;
+
+/// How far a controller axis has to be moved to count as pressed.
+pub const AXIS_THRESHOLD: i16 = 16_384;
+
+/// Reads the input of the game from the keyboard and all connected game
+/// controllers.
+pub struct InputDevices {
+    subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>,
+    controls: Controls,
+}
+
+impl InputDevices {
+    pub fn new(subsystem: GameControllerSubsystem, controls: Controls) -> InputDevices {
+        for binding in ACTIONS.iter().flat_map(|&action| controls.bindings(action)) {
+            let known = match binding {
+                Binding::Key(name) => Keycode::from_name(name).is_some(),
+                Binding::Button(name) => Button::from_string(name).is_some(),
+                Binding::Axis { name, .. } => Axis::from_string(name).is_some(),
+            };
+            if !known {
+                eprintln!("warning: ignoring unknown binding {:?}", binding);
+            }
+        }
+        let mut input_devices = InputDevices { subsystem, controllers: vec![], controls };
+        for joystick_index in 0..input_devices.subsystem.num_joysticks().unwrap_or(0) {
+            input_devices.open(joystick_index);
+        }
+        input_devices
+    }
+
+    /// Opens game controllers when they are connected and closes them when
+    /// they are disconnected.
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => self.open(which),
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers.retain(|controller| controller.instance_id() != which);
+            }
+            _ => {}
+        }
+    }
+
+    fn open(&mut self, joystick_index: u32) {
+        if !self.subsystem.is_game_controller(joystick_index) {
+            return;
+        }
+        // SDL also reports the controllers that are connected at startup.
+        if let Ok(controller) = self.subsystem.open(joystick_index) {
+            if self.controllers.iter().all(|c| c.instance_id() != controller.instance_id()) {
+                self.controllers.push(controller);
+            }
+        }
+    }
+
+    pub fn read(&self, event_pump: &EventPump) -> Input {
+        let pressed_keys: Vec<String> = event_pump.keyboard_state().pressed_scancodes()
+            .filter_map(Keycode::from_scancode)
+            .map(Keycode::name)
+            .collect();
+        self.controls.input(|binding| match binding {
+            Binding::Key(name) => pressed_keys.iter().any(|key| key == name),
+            Binding::Button(name) => match Button::from_string(name) {
+                Some(button) => self.controllers.iter().any(|controller| controller.button(button)),
+                None => false,
+            },
+            Binding::Axis { name, positive } => match Axis::from_string(name) {
+                Some(axis) => self.controllers.iter().any(|controller| {
+                    let value = controller.axis(axis);
+                    if *positive { value > AXIS_THRESHOLD } else { value < -AXIS_THRESHOLD }
+                }),
+                None => false,
+            },
+        })
+    }
+}
@@ -0,0 +1,123 @@
+//! Rendering moving objects between two ticks, so that the frontend can draw
+//! frames at the refresh rate of the display while the game runs at a fixed
+//! number of ticks per second.
+
+use std::time::Duration;
+
+use crate::level::{Cell, Direction, Level};
+
+/// The number of ticks the game runs per second.
+pub const TICKS_PER_SECOND: u32 = 15;
+
+/// At most this many ticks are run at once, e.g., after the window has been
+/// dragged, so the game does not try to catch up for seconds.
+const MAX_TICKS_PER_FRAME: u32 = 5;
+
+/// Decides when to run the next tick of the game, independent of how often
+/// frames are drawn.
+pub struct FixedTimestep {
+    tick_duration: Duration,
+    accumulated: Duration,
+}
+
+impl FixedTimestep {
+    /// Runs `ticks_per_second` ticks per second of real time.
+    pub fn new(ticks_per_second: u32) -> FixedTimestep {
+        FixedTimestep {
+            tick_duration: Duration::from_secs(1) / ticks_per_second,
+            accumulated: Duration::from_secs(0),
+        }
+    }
+
+    /// Adds the time elapsed since the last frame and returns the number of
+    /// ticks that are due.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulated += elapsed;
+        let mut ticks = 0;
+        while self.accumulated >= self.tick_duration {
+            self.accumulated -= self.tick_duration;
+            ticks += 1;
+        }
+        if ticks > MAX_TICKS_PER_FRAME {
+            self.accumulated = Duration::from_secs(0);
+            ticks = MAX_TICKS_PER_FRAME;
+        }
+        ticks
+    }
+
+    /// The fraction of the next tick that has already elapsed, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        self.accumulated.as_secs_f32() / self.tick_duration.as_secs_f32()
+    }
+}
+
+
+/// Returns the offset (in tiles) from its cell at which the object at (x, y)
+/// is drawn, `alpha` of the way from the previous to the current tick.
+///
+/// A move takes two ticks: after the first tick the object is in its new cell
+/// with `moving_in_from` set, after the second tick it has arrived.  Drawing
+/// one tick behind the simulation, the object moves from its old cell to the
+/// middle during the first tick and from the middle to its new cell during the
+/// second tick.
+pub fn object_offset(previous: &Level, current: &Level, x: u16, y: u16, alpha: f32) -> (f32, f32) {
+    let cell = match current.cell_checked(x, y) {
+        Some(cell) => cell,
+        None => return (0.0, 0.0),
+    };
+    let (direction, distance) = if let Some(direction) = cell.moving_in_from {
+        (direction, 1.0 - 0.5 * alpha)
+    } else {
+        let same_size = previous.width == current.width && previous.height == current.height;
+        match previous.cell_checked(x, y) {
+            Some(&Cell { moving_in_from: Some(direction), .. }) if same_size && cell.object.is_some() => {
+                (direction, 0.5 * (1.0 - alpha))
+            }
+            _ => return (0.0, 0.0),
+        }
+    };
+    match direction {
+        Direction::North => (0.0, -distance),
+        Direction::East => (distance, 0.0),
+        Direction::South => (0.0, distance),
+        Direction::West => (-distance, 0.0),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::objects::Object;
+
+    use super::*;
+
+    #[test]
+    fn test_fixed_timestep() {
+        let mut timestep = FixedTimestep::new(10);
+        assert_eq!(timestep.advance(Duration::from_millis(50)), 0);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(timestep.advance(Duration::from_millis(175)), 2);
+        assert!((timestep.alpha() - 0.25).abs() < 1e-6);
+        assert_eq!(timestep.advance(Duration::from_secs(10)), MAX_TICKS_PER_FRAME);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn test_object_offset() {
+        let mut before = Level::new(3, 1);
+        before.set_cell(0, 0, Cell::new(0, 0, Some(Object::Boulder)));
+        let mut moving = Level::new(3, 1);
+        moving.set_cell(1, 0, Cell { moving_in_from: Some(Direction::West), ..Cell::new(1, 0, Some(Object::Boulder)) });
+        let mut arrived = Level::new(3, 1);
+        arrived.set_cell(1, 0, Cell::new(1, 0, Some(Object::Boulder)));
+
+        assert_eq!(object_offset(&before, &moving, 1, 0, 0.0), (-1.0, 0.0));
+        assert_eq!(object_offset(&before, &moving, 1, 0, 0.5), (-0.75, 0.0));
+        assert_eq!(object_offset(&moving, &arrived, 1, 0, 0.0), (-0.5, 0.0));
+        assert_eq!(object_offset(&moving, &arrived, 1, 0, 1.0), (0.0, 0.0));
+        assert_eq!(object_offset(&arrived, &arrived, 1, 0, 0.5), (0.0, 0.0));
+
+        // the object has been removed when it arrived
+        assert_eq!(object_offset(&moving, &Level::new(3, 1), 1, 0, 0.5), (0.0, 0.0));
+    }
+}
@@ -1,25 +1,105 @@
+//! Levels, their cells and the level files (`LEVEL###.BTN`).
+
 use std::convert::TryInto;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
 use std::path::Path;
 
+use crate::error::Error;
 use crate::objects::Object;
 
-#[derive(Copy, Clone, Debug)]
-pub enum Direction { North, East, South, West }
+/// A direction on the map, north is up.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Direction {
+    /// Up.
+    North,
+    /// Right.
+    East,
+    /// Down.
+    South,
+    /// Left.
+    West,
+}
+
+impl Direction {
+    /// The direction 90 degrees counterclockwise.
+    pub fn turn_left(self) -> Direction {
+        match self {
+            Direction::North => Direction::West,
+            Direction::East => Direction::North,
+            Direction::South => Direction::East,
+            Direction::West => Direction::South,
+        }
+    }
+
+    /// The direction 90 degrees clockwise.
+    pub fn turn_right(self) -> Direction {
+        self.turn_left().opposite()
+    }
+
+    /// The direction 180 degrees around.
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+        }
+    }
+}
+
+/// The default background tile.  Empty cells in level files are either this
+/// tile or `ALTERNATIVE_BACKGROUND`.
+pub const BACKGROUND: u8 = 34;
+/// The other background tile, it is kept when the level is saved.
+pub const ALTERNATIVE_BACKGROUND: u8 = 189;
 
-#[derive(Copy, Clone, Debug)]
+/// A cell of the map with its object and the state of the object in the
+/// current tick.
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Cell {
+    /// The column of the cell.
     pub x: u16,
+    /// The row of the cell.
     pub y: u16,
+    /// The object in the cell, `None` if the cell is empty.
     pub object: Option<Object>,
+    /// An object is moving into the cell and arrives in the next tick.
     pub pre_occupied: bool,
+    /// An object is moving out of the cell and leaves it in the next tick.
     pub post_occupied: bool,
+    /// The cell has changed in the current tick and is not updated again.
     pub changed_in_current_tick: bool,
+    /// The direction the object is moving in from.
     pub moving_in_from: Option<Direction>,
+    /// The object is falling.
+    pub falling: bool,
+    /// The tile number of the background that is drawn in empty cells and
+    /// behind transparent objects.
+    pub background: u8,
+    /// The tile number the cell has been loaded from.  It is written back as
+    /// long as it still stands for the object of the cell, e.g., to keep the
+    /// character of a letter or the animation frame of the player.
+    pub loaded_tile_number: Option<u8>,
 }
 
 impl Cell {
+    /// A cell at (x, y) with the object and the default background.
+    pub fn new(x: u16, y: u16, object: Option<Object>) -> Cell {
+        Cell {
+            x,
+            y,
+            object,
+            pre_occupied: false,
+            post_occupied: false,
+            changed_in_current_tick: false,
+            moving_in_from: None,
+            falling: false,
+            background: BACKGROUND,
+            loaded_tile_number: None,
+        }
+    }
+
+    /// Whether the background shows through the cell.
     pub fn is_transparent(self) -> bool {
         match self.object {
             Some(object) => object.is_transparent(),
@@ -27,80 +107,221 @@ impl Cell {
         }
     }
 
+    /// Whether the player can enter the cell.
     pub fn can_be_entered(self) -> bool {
         match self.object {
             Some(object) => object.can_be_entered(),
             None => true,
         }
     }
+
+    /// A cell is free if it is empty and no object is just leaving it.
+    pub fn is_free(self) -> bool {
+        self.object.is_none() && !self.post_occupied
+    }
 }
 
 /// A level contains a map, i.e., a collection of row-by-row tile indices.  The
 /// width and height are the number of tiles per row respectively column of the
 /// map; therefore, the map should have width*height entries.
+#[derive(Clone)]
 pub struct Level {
+    /// The number of columns.
     pub width: u16,
+    /// The number of rows.
     pub height: u16,
+    /// The cells row by row.
     pub map: Vec<Cell>,
 }
 
 impl Level {
+    /// Creates a level with only empty cells.
+    pub fn new(width: u16, height: u16) -> Level {
+        let mut map = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                map.push(Cell::new(x, y, None));
+            }
+        }
+        Level { width, height, map }
+    }
+
+    /// Changes the size of the map.  Cells that are still inside the map are
+    /// kept, new cells are empty.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        let mut level = Level::new(width, height);
+        for cell in &self.map {
+            if cell.x < width && cell.y < height {
+                level.set_cell(cell.x, cell.y, *cell);
+            }
+        }
+        *self = level;
+    }
+
+    /// Iterates over all cells row by row.
+    pub fn cells(&self) -> impl Iterator<Item = &Cell> {
+        self.map.iter()
+    }
+
+    /// Iterates over the rows of the map from top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.map.chunks(self.width.max(1) as usize)
+    }
+
+    /// Returns the cell at (x, y) without checking the column, a column beyond
+    /// the width wraps into the next row.  Use `cell_checked` for positions
+    /// that may be outside of the map.
     pub fn cell(&self, x: u16, y: u16) -> Option<&Cell> {
-        self.map.get((y * self.width + x) as usize)
+        self.map.get(y as usize * self.width as usize + x as usize)
     }
 
+    /// Returns the cell at (x, y) or `None` if it is outside of the map.
     pub fn cell_checked(&self, x: u16, y: u16) -> Option<&Cell> {
-        if x >= 0 && x < self.width && y >= 0 && y < self.height {
-            self.map.get((y * self.width + x) as usize)
+        if x < self.width && y < self.height {
+            self.cell(x, y)
         } else {
             None
         }
     }
 
+    /// Replaces the cell at (x, y), positions outside of the map are ignored.
     pub fn set_cell(&mut self, x: u16, y: u16, cell: Cell) {
-        if let Some(cell_ref) = self.map.get_mut((y * self.width + x) as usize) {
+        if let Some(cell_ref) = self.map.get_mut(y as usize * self.width as usize + x as usize) {
             *cell_ref = cell;
         }
     }
+
+    /// Returns the position of the neighbor of (x, y) in the given direction
+    /// or `None` if the neighbor would be outside of the map.
+    pub fn neighbor_position(&self, x: u16, y: u16, direction: Direction) -> Option<(u16, u16)> {
+        match direction {
+            Direction::North if y >= 1 => Some((x, y - 1)),
+            Direction::East if x < self.width - 1 => Some((x + 1, y)),
+            Direction::South if y < self.height - 1 => Some((x, y + 1)),
+            Direction::West if x >= 1 => Some((x - 1, y)),
+            _ => None,
+        }
+    }
+
+    /// Returns the neighbor of (x, y) in the given direction or `None` if it
+    /// would be outside of the map.
+    pub fn neighbor(&self, x: u16, y: u16, direction: Direction) -> Option<&Cell> {
+        self.neighbor_position(x, y, direction).and_then(|(x, y)| self.cell_checked(x, y))
+    }
+
+    /// Whether (x, y) is in the outermost row or column of the map.
+    pub fn is_border(&self, x: u16, y: u16) -> bool {
+        x == 0 || y == 0 || x == self.width - 1 || y == self.height - 1
+    }
+
+    /// Dirt and walls are drawn with borders towards their neighbors: dirt has
+    /// a border towards every transparent neighbor, walls have a border towards
+    /// every neighbor that is not a wall.
+    pub fn update_neighbors(&mut self) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.update_cell_neighbors(x, y);
+            }
+        }
+    }
+
+    /// Updates the borders of the cell at (x, y) and of its neighbors after the
+    /// cell has changed.
+    pub fn update_neighbors_around(&mut self, x: u16, y: u16) {
+        self.update_cell_neighbors(x, y);
+        for &direction in &[Direction::North, Direction::East, Direction::South, Direction::West] {
+            if let Some((x, y)) = self.neighbor_position(x, y, direction) {
+                self.update_cell_neighbors(x, y);
+            }
+        }
+    }
+
+    fn update_cell_neighbors(&mut self, x: u16, y: u16) {
+        let mut cell = match self.cell_checked(x, y) {
+            Some(&cell) => cell,
+            None => return,
+        };
+        let has_border = |direction| -> Option<bool> {
+            let neighbor = self.neighbor(x, y, direction)?;
+            match cell.object {
+                Some(Object::Dirt { .. }) => Some(neighbor.is_transparent()),
+                Some(Object::Wall { .. }) => Some(!matches!(neighbor.object, Some(Object::Wall { .. }))),
+                _ => None,
+            }
+        };
+        let borders = [
+            has_border(Direction::North),
+            has_border(Direction::East),
+            has_border(Direction::South),
+            has_border(Direction::West),
+        ];
+        match cell.object {
+            Some(Object::Dirt { ref mut north, ref mut east, ref mut south, ref mut west, .. })
+            | Some(Object::Wall { ref mut north, ref mut east, ref mut south, ref mut west, .. }) => {
+                for (flag, border) in [north, east, south, west].iter_mut().zip(borders.iter()) {
+                    if let Some(border) = *border {
+                        **flag = border;
+                    }
+                }
+            }
+            _ => return,
+        }
+        self.set_cell(x, y, cell);
+    }
 }
 
 
 /// A level file is composed of a header (width and height) and the map data
 /// (width*height) entries.
-pub fn load_level(path: &Path) -> Result<Level, String> {
-    let mut file = File::open(path).unwrap();
-    let mut buffer = vec!();
-    file.read_to_end(&mut buffer).unwrap();
+pub fn load_level(path: &Path) -> Result<Level, Error> {
+    let buffer = match fs::read(path) {
+        Ok(buffer) => buffer,
+        Err(reason) => return Err(Error::io(path, reason)),
+    };
     if buffer.len() < 4 {
-        return Err(format!("missing header (width and height) in level file {}", path.display()));
+        return Err(Error::TruncatedHeader { path: path.into(), offset: 0 });
     }
     let width = u16::from_le_bytes(buffer[..2].try_into().unwrap());
     let height = u16::from_le_bytes(buffer[2..4].try_into().unwrap());
-    if 4 + width as usize * height as usize == buffer.len() {
-        Ok(Level {
-            width,
-            height,
-            map: buffer[4..].iter().enumerate().map(
-                |(i, &x)| Cell {
-                    x: i as u16 % width,
-                    y: i as u16 / width,
-                    object: match Object::from_tile_number(x).unwrap() {
-                        Object::Empty => None,
-                        object => Some(object),
-                    },
-                    pre_occupied: false,
-                    post_occupied: false,
-                    changed_in_current_tick: false,
-                    moving_in_from: None,
-                }
-            ).collect(),
-        })
-    } else {
-        Err(format!(
-            "according to header, level file {} should contain 4+{}*{}={} bytes, found {} bytes",
-            path.display(), width, height, 4 + width * height, buffer.len(),
-        ))
+    let expected = 4 + width as usize * height as usize;
+    if expected != buffer.len() {
+        return Err(Error::SizeMismatch { path: path.into(), offset: 0, expected, found: buffer.len() });
+    }
+    let mut map = Vec::with_capacity(buffer.len() - 4);
+    for (i, &tile_number) in buffer[4..].iter().enumerate() {
+        let object = match Object::from_tile_number(tile_number) {
+            Some(Object::Empty) => None,
+            Some(object) => Some(object),
+            None => return Err(Error::InvalidTileNumber { path: path.into(), offset: 4 + i, tile_number }),
+        };
+        map.push(Cell {
+            background: if tile_number == ALTERNATIVE_BACKGROUND { tile_number } else { BACKGROUND },
+            loaded_tile_number: Some(tile_number),
+            ..Cell::new((i % width as usize) as u16, (i / width as usize) as u16, object)
+        });
     }
+    Ok(Level { width, height, map })
+}
+
+
+/// Serializes the level to the format described at `load_level`.  Every tile
+/// that has been loaded from a level file is written back as the same tile.
+fn serialize_level(level: &Level) -> Vec<u8> {
+    let mut buffer = vec!();
+    buffer.extend_from_slice(&level.width.to_le_bytes());
+    buffer.extend_from_slice(&level.height.to_le_bytes());
+    buffer.extend(level.map.iter().map(|cell| match (cell.object, cell.loaded_tile_number) {
+        (Some(object), Some(tile_number)) if Object::from_tile_number(tile_number) == Some(object) => tile_number,
+        (Some(object), _) => object.to_tile_number(),
+        (None, _) => cell.background,
+    }));
+    buffer
+}
+
+
+/// Writes the level in the format of `load_level`.
+pub fn save_level(path: &Path, level: &Level) -> Result<(), Error> {
+    fs::write(path, serialize_level(level)).map_err(|reason| Error::io(path, reason))
 }
 
 
@@ -108,6 +329,8 @@ pub fn load_level(path: &Path) -> Result<Level, String> {
 mod test {
     use std::io::Write;
 
+    use proptest::collection::vec;
+    use proptest::prelude::*;
     use tempfile::{NamedTempFile, TempPath};
 
     use super::*;
@@ -120,25 +343,26 @@ mod test {
 
     #[test]
     fn test_load() {
+        // missing file
+        let directory = tempfile::tempdir().unwrap();
+        let missing_path = directory.path().join("LEVEL001.BTN");
+        assert!(matches!(load_level(&missing_path), Err(Error::Io { path, .. }) if path == missing_path));
+
         // missing header, requires at least 4 bytes
         let path = create_temp_file(&[9]);
-        assert_eq!(load_level(&path).err(), Some(format!(
-            "missing header (width and height) in level file {}", path.display(),
-        )));
+        assert!(matches!(load_level(&path), Err(Error::TruncatedHeader { offset: 0, .. })));
 
         // map data too short
         let path = create_temp_file(&[9, 0, 2, 0, 99, 99]);
-        assert_eq!(load_level(&path).err(), Some(format!(
-            "according to header, level file {} should contain 4+9*2=22 bytes, found 6 bytes",
-            path.display(),
-        )));
+        assert!(matches!(load_level(&path), Err(Error::SizeMismatch { offset: 0, expected: 22, found: 6, .. })));
 
         // map data too long
         let path = create_temp_file(&[1, 0, 1, 0, 99, 99]);
-        assert_eq!(load_level(&path).err(), Some(format!(
-            "according to header, level file {} should contain 4+1*1=5 bytes, found 6 bytes",
-            path.display(),
-        )));
+        assert!(matches!(load_level(&path), Err(Error::SizeMismatch { offset: 0, expected: 5, found: 6, .. })));
+
+        // invalid tile number
+        let path = create_temp_file(&[2, 0, 1, 0, 34, 250]);
+        assert!(matches!(load_level(&path), Err(Error::InvalidTileNumber { offset: 5, tile_number: 250, .. })));
 
         // success
         let path = create_temp_file(&[
@@ -148,6 +372,124 @@ mod test {
         let level = load_level(&path).unwrap();
         assert_eq!(level.width, 4);
         assert_eq!(level.height, 3);
-        assert_eq!(level.map, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(
+            level.map.iter().map(|cell| (cell.x, cell.y, cell.object)).collect::<Vec<_>>(),
+            (1..=12).map(|tile_number: u8| (
+                (tile_number as u16 - 1) % 4,
+                (tile_number as u16 - 1) / 4,
+                Object::from_tile_number(tile_number),
+            )).collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn test_load_large_level() {
+        // more cells than fit into a u16
+        let mut data = vec![];
+        data.extend_from_slice(&300u16.to_le_bytes());
+        data.extend_from_slice(&250u16.to_le_bytes());
+        data.resize(4 + 300 * 250, BACKGROUND);
+        let path = create_temp_file(&data);
+        let level = load_level(&path).unwrap();
+        let last = level.map.last().unwrap();
+        assert_eq!((last.x, last.y), (299, 249));
+        assert_eq!(level.cell(299, 249), Some(last));
+    }
+
+    #[test]
+    fn test_save() {
+        let path = create_temp_file(&[
+            3, 0, 2, 0,
+            32, 5, 33,
+            34, 189, 36,
+        ]);
+        let mut level = load_level(&path).unwrap();
+        assert_eq!(level.cell(0, 0).unwrap().object, Some(Object::Dirt {
+            north: false, east: false, south: false, west: false, generic: true,
+        }));
+        assert_eq!(level.cell(1, 0).unwrap().object, Some(Object::Dirt {
+            north: true, east: false, south: true, west: false, generic: false,
+        }));
+        assert_eq!(level.cell(1, 1).unwrap().background, ALTERNATIVE_BACKGROUND);
+
+        // changed cells are written with their own tile number
+        level.set_cell(1, 1, Cell::new(1, 1, Some(Object::Boulder)));
+        level.set_cell(2, 1, Cell::new(2, 1, None));
+        let saved_path = NamedTempFile::new().unwrap().into_temp_path();
+        save_level(&saved_path, &level).unwrap();
+        assert_eq!(fs::read(&saved_path).unwrap(), vec![
+            3, 0, 2, 0,
+            32, 5, 33,
+            34, 118, 34,
+        ]);
+    }
+
+    #[test]
+    fn test_resize() {
+        let path = create_temp_file(&[
+            2, 0, 2, 0,
+            118, 119,
+            39, 34,
+        ]);
+        let mut level = load_level(&path).unwrap();
+        level.resize(3, 1);
+        assert_eq!(level.width, 3);
+        assert_eq!(level.height, 1);
+        assert_eq!(
+            level.map.iter().map(|cell| (cell.x, cell.y, cell.object)).collect::<Vec<_>>(),
+            vec![(0, 0, Some(Object::Boulder)), (1, 0, Some(Object::Balloon)), (2, 0, None)],
+        );
+        level.resize(1, 2);
+        assert_eq!(
+            level.map.iter().map(|cell| (cell.x, cell.y, cell.object)).collect::<Vec<_>>(),
+            vec![(0, 0, Some(Object::Boulder)), (0, 1, None)],
+        );
+    }
+
+    #[test]
+    fn test_cells() {
+        let path = create_temp_file(&[
+            3, 0, 2, 0,
+            118, 119, 34,
+            39, 34, 118,
+        ]);
+        let level = load_level(&path).unwrap();
+        assert_eq!(level.cells().filter(|cell| cell.object == Some(Object::Boulder)).count(), 2);
+        let rows: Vec<Vec<_>> = level.rows().map(|row| row.iter().map(|cell| (cell.x, cell.y)).collect()).collect();
+        assert_eq!(rows, vec![vec![(0, 0), (1, 0), (2, 0)], vec![(0, 1), (1, 1), (2, 1)]]);
+    }
+
+    #[test]
+    fn test_load_save_every_tile() {
+        // a 16x12 level with every tile number from 0 to 191 exactly once
+        let mut data = vec![16, 0, 12, 0];
+        data.extend(0..=191u8);
+        let path = create_temp_file(&data);
+        let level = load_level(&path).unwrap();
+        let saved_path = NamedTempFile::new().unwrap().into_temp_path();
+        save_level(&saved_path, &level).unwrap();
+        assert_eq!(fs::read(&saved_path).unwrap(), data);
+    }
+
+    /// All valid tile numbers.
+    fn round_trip_tile_number() -> impl Strategy<Value=u8> {
+        0..=191u8
+    }
+
+    proptest! {
+        #[test]
+        fn test_load_save_round_trip(
+            (width, height, tiles) in (1..40u16, 1..40u16).prop_flat_map(|(width, height)| {
+                (Just(width), Just(height), vec(round_trip_tile_number(), width as usize * height as usize))
+            })
+        ) {
+            let mut data = vec![];
+            data.extend_from_slice(&width.to_le_bytes());
+            data.extend_from_slice(&height.to_le_bytes());
+            data.extend_from_slice(&tiles);
+            let path = create_temp_file(&data);
+            let level = load_level(&path).unwrap();
+            prop_assert_eq!(serialize_level(&level), data);
+        }
     }
 }
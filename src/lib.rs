@@ -0,0 +1,25 @@
+//! Loaders, writers and the game simulation of Crystal Mines episodes.  The
+//! crate does not depend on a frontend: a `Game` is advanced tick by tick with
+//! an `Input` and reports what happened as `GameEvent`s, while the files of an
+//! episode (levels, tiles, palettes, high scores and the progress of a
+//! campaign) are read and written with the functions of their modules.
+
+#![warn(missing_docs)]
+
+pub mod camera;
+pub mod campaign;
+pub mod controls;
+pub mod display;
+pub mod error;
+pub mod game;
+pub mod high_score_table;
+pub mod hud;
+pub mod interpolation;
+pub mod level;
+pub mod objects;
+pub mod palette;
+pub mod sound;
+pub mod tile_sheet;
+pub mod tiles;
+
+pub use crate::error::Error;
@@ -1,42 +1,99 @@
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::exit;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::{BlendMode, Texture, WindowCanvas};
-use crate::objects::Object;
-use crate::level::{Cell, Direction, Level};
-
-mod high_score_table;
-mod tiles;
-mod palette;
-mod level;
-mod objects;
-
+use sdl2::EventPump;
+use sdl2::render::{BlendMode, Texture, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+use cm_rust::camera::Camera;
+use cm_rust::campaign::Campaign;
+use cm_rust::controls::{self, Binding, Controls, ACTIONS};
+use cm_rust::game::{Game, Status, DEATH_ANIMATION_TICKS};
+use cm_rust::high_score_table::{self, HighScore, HighScoreTable};
+use cm_rust::hud::{self, HUD_ROWS};
+use cm_rust::interpolation::{self, FixedTimestep, TICKS_PER_SECOND};
+use cm_rust::level::{self, Level, BACKGROUND};
+use cm_rust::objects::{self, Object};
+use cm_rust::tiles::{self, Tile};
+use cm_rust::{palette, tile_sheet, Error};
+use crate::audio::{Audio, AUDIO_KEYS};
+use crate::input::{InputDevices, AXIS_THRESHOLD};
+use crate::screen::Screen;
+
+mod audio;
+mod editor;
+mod input;
+mod screen;
+
+
+/// The number of tiles the logical resolution of the game and the editor is
+/// wide and high.
+const COLUMNS: u32 = 30;
+const ROWS: u32 = 20;
+
+/// Frames are drawn at most this often, in case presenting a frame does not
+/// wait for the vertical sync.
+const MAX_FRAMES_PER_SECOND: u32 = 120;
+
+/// The bindings of keys and game controllers to the actions of the game.
+const CONTROLS_PATH: &str = "controls.cfg";
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() >= 2 {
         match &args[1][..] {
-            "high-scores" => {
-                if args.len() == 3 {
-                    high_scores(&args[2]);
+            "high-scores" if args.len() == 3 => {
+                high_scores(&args[2]);
+                return;
+            }
+            "tiles" if args.len() == 3 => {
+                tiles(&args[2]);
+                return;
+            }
+            "tiles" if args.len() == 5 && args[2] == "export" => {
+                export_tiles(&args[3], Path::new(&args[4]));
+                return;
+            }
+            "tiles" if args.len() == 5 && args[2] == "import" => {
+                import_tiles(&args[3], Path::new(&args[4]));
+                return;
+            }
+            "palette" if args.len() == 3 => {
+                show_palette(&args[2]);
+                return;
+            }
+            "palette" if args.len() == 5 && args[2] == "import" => {
+                import_palette(&args[3], Path::new(&args[4]));
+                return;
+            }
+            "start" if args.len() == 4 => {
+                if let Ok(level_number) = args[3].parse() {
+                    start(&args[2], level_number);
                     return;
                 }
             }
-            "tiles" => {
-                if args.len() == 3 {
-                    tiles(&args[2]);
+            "play" if args.len() == 3 => {
+                play(&args[2]);
+                return;
+            }
+            "copy-level" if args.len() == 5 => {
+                if let Ok(level_number) = args[3].parse() {
+                    copy_level(&args[2], level_number, Path::new(&args[4]));
                     return;
                 }
             }
-            "start" => {
-                if args.len() == 4 {
-                    start(&args[2], args[3].parse().unwrap());
+            "controls" if args.len() == 3 => {
+                remap_controls(&args[2]);
+                return;
+            }
+            "edit" if args.len() == 4 => {
+                if let Ok(level_number) = args[3].parse() {
+                    edit(&args[2], level_number);
                     return;
                 }
             }
@@ -48,65 +105,92 @@ fn main() {
 }
 
 
-fn high_scores(episode: &str) {
-    let path = Path::new(".").join("MINING").join(episode).join("high.dat");
-    if !path.exists() {
-        eprintln!("high scores file of {} does not exist", episode);
-        exit(1);
-    }
-    let high_scores = high_score_table::load(&path).unwrap();
-    for (i, high_score) in high_scores.iter().enumerate() {
-        println!("{}. {:?}", i + 1, high_score);
-    }
+fn episode_directory(episode: &str) -> PathBuf {
+    Path::new(".").join("MINING").join(episode)
 }
 
 
-fn tiles(episode: &str) {
-    let palette_path = Path::new(".").join("MINING").join(episode).join(format!("{}.PAL", episode.to_uppercase()));
-    if !palette_path.exists() {
-        eprintln!("palette file of {} does not exist", episode);
-        exit(1);
+/// Returns the path of a file in the directory of the episode.
+fn episode_file(episode: &str, name: &str) -> PathBuf {
+    episode_directory(episode).join(name)
+}
+
+
+fn palette_path(episode: &str) -> PathBuf {
+    episode_file(episode, &format!("{}.PAL", episode.to_uppercase()))
+}
+
+
+/// Loads the palette and the tiles of the episode or exits.
+fn load_palette_and_tiles(episode: &str) -> (Vec<Vec<u8>>, Vec<Tile>) {
+    let palette = exit_on_error(palette::load_palette(&palette_path(episode)));
+    let tiles = exit_on_error(tiles::load_tiles(&episode_file(episode, "TILE.DAT")));
+    (palette, tiles)
+}
+
+
+/// Sleeps for the rest of the frame that started at `frame_start` if it
+/// finished early.
+fn limit_frame_rate(frame_start: Instant) {
+    let frame_duration = Duration::from_secs(1) / MAX_FRAMES_PER_SECOND;
+    let elapsed = frame_start.elapsed();
+    if elapsed < frame_duration {
+        ::std::thread::sleep(frame_duration - elapsed);
     }
-    let tiles_path = Path::new(".").join("MINING").join(episode).join("TILE.DAT");
-    if !tiles_path.exists() {
-        eprintln!("tile file of {} does not exist", episode);
+}
+
+
+/// Returns the value or prints the error and exits.
+fn exit_on_error<T>(result: Result<T, Error>) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("error: {}", error);
         exit(1);
+    })
+}
+
+
+fn high_scores(episode: &str) {
+    let path = episode_file(episode, "high.dat");
+    let high_scores = exit_on_error(high_score_table::load(&path));
+    for (i, high_score) in high_scores.high_scores.iter().enumerate() {
+        println!("{}. {} {}", i + 1, high_score.name(), high_score.score());
     }
+}
+
 
+/// Writes a copy of the level, e.g., as the start of a new level.  Every tile
+/// is written back unchanged.
+fn copy_level(episode: &str, level_number: u16, path: &Path) {
+    let level_path = episode_file(episode, &format!("LEVEL{:03}.BTN", level_number));
+    let level = exit_on_error(level::load_level(&level_path));
+    exit_on_error(level::save_level(path, &level));
+}
+
+
+fn tiles(episode: &str) {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let window = video_subsystem.window("", 660, 500)
-        .position_centered()
-        .build()
-        .unwrap();
+    let (palette, tiles) = load_palette_and_tiles(episode);
 
-    let mut canvas = window.into_canvas().build().unwrap();
+    // Every tile gets a cell as large as the largest tile.
+    let cell_width = tiles.iter().map(|tile| tile.width as u32).max().unwrap_or(1);
+    let cell_height = tiles.iter().map(|tile| tile.height as u32).max().unwrap_or(1);
+    let rows = tiles.len() as u32 / 16 + 1;
+    let (mut canvas, mut screen) = Screen::open(&video_subsystem, "", 16 * cell_width, rows * cell_height);
 
     canvas.set_draw_color(Color::RGB(127, 127, 255));
-    canvas.clear();
+    screen.clear(&mut canvas);
     canvas.present();
 
-    let palette = palette::load_palette(&palette_path).unwrap();
-    let tiles = tiles::load_tiles(&tiles_path).unwrap();
-
     let texture_creator = canvas.texture_creator();
-    let mut tile_textures = vec![];
-    for tile in &tiles {
-        let pixels: Vec<u8> = tile.data.iter().map(|&v| palette[v as usize].clone()).flatten().collect();
-        let pixels = &pixels[..];
-        let mut texture: Texture = texture_creator
-            .create_texture_target(PixelFormatEnum::RGBA32, tile.width as u32, tile.height as u32)
-            .unwrap();
-        texture.set_blend_mode(BlendMode::Blend);
-        texture.update(None, pixels, tile.width as usize * 4).unwrap();
-        tile_textures.push((tile.width, tile.height, texture));
-    }
+    let tile_textures = create_tile_textures(&texture_creator, &tiles, &palette);
 
     let mut running = true;
     while running {
         for event in event_pump.poll_iter() {
+            screen.handle_event(&mut canvas, &event);
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => running = false,
@@ -114,16 +198,16 @@ fn tiles(episode: &str) {
             }
         }
 
-        canvas.clear();
+        screen.clear(&mut canvas);
         for y in 0..tiles.len() / 16 + 1 {
             for x in 0..16 {
-                let pos = (y * 16 + x) as usize;
+                let pos = y * 16 + x;
                 if pos < tiles.len() {
-                    let (width, height, tile_texture) = &tile_textures[pos as usize];
+                    let (width, height, tile_texture) = &tile_textures[pos];
                     canvas.copy(
                         tile_texture,
                         None,
-                        Rect::new(x as i32 * *width as i32, y as i32 * *height as i32, *width as u32, *height as u32),
+                        Rect::new((x as u32 * cell_width) as i32, (y as u32 * cell_height) as i32, *width as u32, *height as u32),
                     ).unwrap();
                 }
             }
@@ -134,286 +218,632 @@ fn tiles(episode: &str) {
 }
 
 
-fn start(episode: &str, level_number: u8) {
-    let palette_path = Path::new(".").join("MINING").join(episode).join(format!("{}.PAL", episode.to_uppercase()));
-    if !palette_path.exists() {
-        eprintln!("palette file of {} does not exist", episode);
-        exit(1);
+/// Prints all colors of the palette and shows them as swatches, 16 per row.
+fn show_palette(episode: &str) {
+    let palette = exit_on_error(palette::load_palette(&palette_path(episode)));
+    for (i, color) in palette.iter().enumerate() {
+        println!("{:3}: {:3} {:3} {:3} #{:02x}{:02x}{:02x}", i, color[0], color[1], color[2], color[0], color[1], color[2]);
     }
-    let tiles_path = Path::new(".").join("MINING").join(episode).join("TILE.DAT");
-    if !tiles_path.exists() {
-        eprintln!("tile file of {} does not exist", episode);
-        exit(1);
+
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    let (mut canvas, mut screen) = Screen::open(&video_subsystem, &format!("{} palette", episode), 16 * 32, 16 * 32);
+
+    let mut running = true;
+    while running {
+        for event in event_pump.poll_iter() {
+            screen.handle_event(&mut canvas, &event);
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => running = false,
+                Event::MouseButtonDown { x, y, .. } => {
+                    let (x, y) = screen.to_logical(x, y);
+                    if x < 0 || y < 0 || x >= 16 * 32 {
+                        continue;
+                    }
+                    let i = (y / 32 * 16 + x / 32) as usize;
+                    if let Some(color) = palette.get(i) {
+                        println!("{:3}: {:3} {:3} {:3} #{:02x}{:02x}{:02x}", i, color[0], color[1], color[2], color[0], color[1], color[2]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        canvas.set_draw_color(Color::RGB(127, 127, 255));
+        screen.clear(&mut canvas);
+        for (i, color) in palette.iter().enumerate() {
+            canvas.set_draw_color(Color::RGB(color[0], color[1], color[2]));
+            canvas.fill_rect(Rect::new((i % 16) as i32 * 32, (i / 16) as i32 * 32, 32, 32)).unwrap();
+        }
+        canvas.present();
+        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 15));
     }
-    let level_path = Path::new(".").join("MINING").join(episode).join(format!("LEVEL{:03}.BTN", level_number));
-    if !level_path.exists() {
-        eprintln!("level {:03} file of {} does not exist", level_number, episode);
-        exit(1);
+}
+
+
+/// Replaces the palette of the episode with a GIMP or JASC palette.
+fn import_palette(episode: &str, path: &Path) {
+    let palette_path = palette_path(episode);
+    let palette = exit_on_error(palette::import_palette(path));
+    exit_on_error(palette::save_palette(&palette_path, &palette));
+    println!("imported {} colors into {}", palette.len(), palette_path.display());
+}
+
+
+/// Writes the tiles of the episode to a sprite sheet with a TOML index next
+/// to it.
+fn export_tiles(episode: &str, png_path: &Path) {
+    let (palette, tiles) = load_palette_and_tiles(episode);
+    let index_path = png_path.with_extension("toml");
+    exit_on_error(tile_sheet::export(&tiles, &palette, png_path, &index_path));
+    println!("exported {} tiles to {} and {}", tiles.len(), png_path.display(), index_path.display());
+}
+
+
+/// Replaces the tiles of the episode with the tiles of an edited sprite sheet.
+fn import_tiles(episode: &str, png_path: &Path) {
+    let tiles_path = episode_file(episode, "TILE.DAT");
+
+    let palette = exit_on_error(palette::load_palette(&palette_path(episode)));
+    let index_path = png_path.with_extension("toml");
+    let tiles = exit_on_error(tile_sheet::import(png_path, &index_path, &palette));
+    exit_on_error(tiles::save_tiles(&tiles_path, &tiles));
+    println!("imported {} tiles into {}", tiles.len(), tiles_path.display());
+}
+
+
+fn start(episode: &str, level_number: u16) {
+    let level_path = episode_file(episode, &format!("LEVEL{:03}.BTN", level_number));
+    let level = exit_on_error(level::load_level(&level_path));
+
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut input = InputDevices::new(sdl_context.game_controller().unwrap(), load_controls());
+    let mut audio = Audio::open(&sdl_context);
+
+    let (palette, tiles) = load_palette_and_tiles(episode);
+    let (width, height) = logical_size(&tiles);
+    let (mut canvas, mut screen) = Screen::open(&video_subsystem, "", width, height);
+
+    canvas.set_draw_color(Color::RGB(127, 127, 255));
+    screen.clear(&mut canvas);
+    canvas.present();
+
+    let texture_creator = canvas.texture_creator();
+    let tile_textures = create_tile_textures(&texture_creator, &tiles, &palette);
+
+    let mut game = Game::new(level);
+    let mut devices = Devices { event_pump: &mut event_pump, input: &mut input, audio: &mut audio };
+    let restart = || exit_on_error(level::load_level(&level_path));
+    if run_level(&mut canvas, &mut screen, &mut devices, &tile_textures, &mut game, &restart, 0) == Status::Completed {
+        println!("level {:03} completed with {} points", level_number, game.score);
     }
+}
+
+fn edit(episode: &str, level_number: u16) {
+    let level_path = episode_file(episode, &format!("LEVEL{:03}.BTN", level_number));
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut input = InputDevices::new(sdl_context.game_controller().unwrap(), load_controls());
+    let mut audio = Audio::open(&sdl_context);
+
+    let (palette, tiles) = load_palette_and_tiles(episode);
+    let (width, height) = logical_size(&tiles);
+    let (mut canvas, mut screen) = Screen::open(&video_subsystem, "", width, height);
+
+    canvas.set_draw_color(Color::RGB(127, 127, 255));
+    screen.clear(&mut canvas);
+    canvas.present();
+
+    let texture_creator = canvas.texture_creator();
+    let tile_textures = create_tile_textures(&texture_creator, &tiles, &palette);
 
-    let window = video_subsystem.window("", 1200, 800)
-        .position_centered()
-        .build()
-        .unwrap();
+    editor::edit(&mut canvas, &mut screen, &mut event_pump, &mut input, &mut audio, &tile_textures, &level_path);
+}
+
+fn play(episode: &str) {
+    let mut campaign = exit_on_error(Campaign::load(&episode_directory(episode)));
+
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut input = InputDevices::new(sdl_context.game_controller().unwrap(), load_controls());
+    let mut audio = Audio::open(&sdl_context);
 
-    let mut canvas = window.into_canvas().build().unwrap();
+    let (palette, tiles) = load_palette_and_tiles(episode);
+    let (width, height) = logical_size(&tiles);
+    let (mut canvas, mut screen) = Screen::open(&video_subsystem, "", width, height);
 
     canvas.set_draw_color(Color::RGB(127, 127, 255));
-    canvas.clear();
+    screen.clear(&mut canvas);
     canvas.present();
 
-    let palette = palette::load_palette(&palette_path).unwrap();
-    let tiles = tiles::load_tiles(&tiles_path).unwrap();
-    let mut level = level::load_level(&level_path).unwrap();
-
-    for pos in 0..level.map.len() {
-        let mut cell = level.map[pos];
-        if let Cell { x, y, object: Some(ref mut object), .. } = cell {
-            match object {
-                Object::Dirt {
-                    ref mut north,
-                    ref mut east,
-                    ref mut south,
-                    ref mut west,
-                } => {
-                    if x >= 1 {
-                        if let Some(neighbor) = level.cell(x - 1, y) {
-                            *west = neighbor.is_transparent();
-                        }
-                    }
-                    if x < level.width - 1 {
-                        if let Some(neighbor) = level.cell(x + 1, y) {
-                            *east = neighbor.is_transparent();
-                        }
-                    }
-                    if y >= 1 {
-                        if let Some(neighbor) = level.cell(x, y - 1) {
-                            *north = neighbor.is_transparent();
-                        }
-                    }
-                    if y < level.height - 1 {
-                        if let Some(neighbor) = level.cell(x, y + 1) {
-                            *south = neighbor.is_transparent();
-                        }
-                    }
-                    level.map[pos] = cell;
+    let texture_creator = canvas.texture_creator();
+    let tile_textures = create_tile_textures(&texture_creator, &tiles, &palette);
+
+    let mut index = match select_level(&mut canvas, &mut screen, &mut event_pump, &tile_textures, &campaign) {
+        Some(index) => index,
+        None => return,
+    };
+    let mut game = Game::new(exit_on_error(campaign.load_level(index)));
+    let mut devices = Devices { event_pump: &mut event_pump, input: &mut input, audio: &mut audio };
+
+    // The score to enter into the high score table once the game is over.
+    let final_score = loop {
+        let restart = || exit_on_error(campaign.load_level(index));
+        match run_level(&mut canvas, &mut screen, &mut devices, &tile_textures, &mut game, &restart, campaign.score) {
+            Status::Completed => match exit_on_error(campaign.complete_level(index, game.score)) {
+                Some(next_index) => {
+                    index = next_index;
+                    game.start_level(exit_on_error(campaign.load_level(index)));
                 }
-                Object::Wall {
-                    ref mut north,
-                    ref mut east,
-                    ref mut south,
-                    ref mut west,
-                } => {
-                    if x >= 1 {
-                        if let Some(neighbor) = level.cell(x - 1, y) {
-                            *west = match neighbor.object {
-                                Some(Object::Wall { .. }) => false,
-                                _ => true
-                            };
-                        }
-                    }
-                    if x < level.width - 1 {
-                        if let Some(neighbor) = level.cell(x + 1, y) {
-                            *east = match neighbor.object {
-                                Some(Object::Wall { .. }) => false,
-                                _ => true
-                            };
-                        }
-                    }
-                    if y >= 1 {
-                        if let Some(neighbor) = level.cell(x, y - 1) {
-                            *north = match neighbor.object {
-                                Some(Object::Wall { .. }) => false,
-                                _ => true
-                            };
-                        }
-                    }
-                    if y < level.height - 1 {
-                        if let Some(neighbor) = level.cell(x, y + 1) {
-                            *south = match neighbor.object {
-                                Some(Object::Wall { .. }) => false,
-                                _ => true
-                            };
-                        }
-                    }
-                    level.map[pos] = cell;
+                None => {
+                    println!("episode {} completed with {} points", episode, campaign.score);
+                    break Some(campaign.score);
                 }
-                _ => (),
+            },
+            // However the GAME OVER screen is left, the game is over.
+            Status::GameOver => break Some(campaign.score + game.score),
+            Status::Running | Status::Dying { .. } | Status::Died => break None,
+        }
+    };
+
+    if let Some(score) = final_score {
+        let high_scores_path = episode_file(episode, "high.dat");
+        let mut high_scores = if high_scores_path.exists() {
+            exit_on_error(high_score_table::load(&high_scores_path))
+        } else {
+            HighScoreTable::new(high_score_table::DEFAULT_CAPACITY)
+        };
+        if high_scores.qualifies(score) {
+            if let Some(name) = enter_name(&mut canvas, &mut screen, &mut event_pump, &tile_textures, score) {
+                high_scores.insert(HighScore::new(&name, score).unwrap());
+                exit_on_error(high_score_table::save(&high_scores_path, &high_scores));
             }
         }
     }
+}
 
-    // let iter_mut = level.map.iter_mut();
-    // for cell in iter_mut {
-    //     // cell.update(&level);
-    //     let a = &level.map[0];
-    // }
 
-    let texture_creator = canvas.texture_creator();
-    let mut tile_textures = vec![];
-    for tile in tiles {
-        let pixels: Vec<u8> = tile.data.iter().map(|&v| palette[v as usize].clone()).flatten().collect();
-        let pixels = &pixels[..];
-        let mut texture: Texture = texture_creator
-            .create_texture_target(PixelFormatEnum::RGBA32, tile.width as u32, tile.height as u32)
-            .unwrap();
-        texture.set_blend_mode(BlendMode::Blend);
-        texture.update(None, pixels, tile.width as usize * 4).unwrap();
-        tile_textures.push((tile.width, tile.height, texture));
-    }
+/// The devices a level is played with.
+struct Devices<'a> {
+    event_pump: &'a mut EventPump,
+    input: &'a mut InputDevices,
+    audio: &'a mut Audio,
+}
 
-    let mut running = true;
+
+/// Plays the level of the game with music until it is completed, the player
+/// leaves the GAME OVER screen with return, or the player quits.  After every
+/// death the level restarts from `restart`.  The HUD shows `base_score` plus
+/// the score of the level.  Returns the status of the game at the end.
+fn run_level(
+    canvas: &mut WindowCanvas,
+    screen: &mut Screen,
+    devices: &mut Devices,
+    tile_textures: &[(u16, u16, Texture)],
+    game: &mut Game,
+    restart: &dyn Fn() -> Level,
+    base_score: u32,
+) -> Status {
+    let mut previous_level = game.level.clone();
+    let mut camera = create_camera(screen, tile_textures);
+    center_camera(&mut camera, game);
+    devices.audio.play_music(true);
+    let mut timestep = FixedTimestep::new(TICKS_PER_SECOND);
+    let mut last_frame = Instant::now();
     let mut tick_number = 0u8;
-    while running {
-        for event in event_pump.poll_iter() {
+    let status = 'running: loop {
+        for event in devices.event_pump.poll_iter() {
+            screen.handle_event(canvas, &event);
+            devices.input.handle_event(&event);
+            devices.audio.handle_event(&event);
             match event {
                 Event::Quit { .. }
-                | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => running = false,
+                | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running game.status,
+                Event::KeyDown { keycode: Some(Keycode::Return), .. } if game.status == Status::GameOver => {
+                    break 'running game.status;
+                }
                 _ => {}
             }
         }
 
-        let mut move_left = false;
-        let mut move_right = false;
-        let mut move_up = false;
-        let mut move_down = false;
-        for keycode in event_pump.keyboard_state().pressed_scancodes().filter_map(Keycode::from_scancode) {
-            match keycode {
-                Keycode::Left => move_left = true,
-                Keycode::Right => move_right = true,
-                Keycode::Up => move_up = true,
-                Keycode::Down => move_down = true,
-                _ => {}
+        let now = Instant::now();
+        for _ in 0..timestep.advance(now - last_frame) {
+            previous_level = game.level.clone();
+            game.step(devices.input.read(devices.event_pump));
+            devices.audio.play_events(&game.events);
+            match game.status {
+                Status::Completed => break 'running game.status,
+                Status::Died => {
+                    game.start_level(restart());
+                    previous_level = game.level.clone();
+                    center_camera(&mut camera, game);
+                }
+                Status::Running | Status::Dying { .. } | Status::GameOver => {}
             }
+            tick_number = (tick_number + 1) % 12;
         }
+        last_frame = now;
 
-        for y in 0..level.height {
-            for x in 0..level.width {
-                if let Some(&cell) = level.cell(x, y) {
-                    if let Cell { object: Some(Object::Player), moving_in_from: Some(_), .. } = cell {
-                        level.set_cell(x, y, Cell {
-                            moving_in_from: None,
-                            changed_in_current_tick: true,
-                            ..cell
-                        })
+        screen.clear(canvas);
+        follow_player(&mut camera, game, &previous_level, timestep.alpha());
+        draw_game(canvas, tile_textures, game, &previous_level, timestep.alpha(), &camera, tick_number);
+        draw_hud(canvas, tile_textures, game, base_score + game.score);
+        canvas.present();
+        limit_frame_rate(last_frame);
+    };
+    devices.audio.play_music(false);
+    status
+}
+
+
+/// Lets the player enter the three letters of their name for the high score
+/// table, either by typing them or by choosing them with the arrow keys.
+fn enter_name(
+    canvas: &mut WindowCanvas,
+    screen: &mut Screen,
+    event_pump: &mut EventPump,
+    tile_textures: &[(u16, u16, Texture)],
+    score: u32,
+) -> Option<String> {
+    let mut name = [b'A'; 3];
+    let mut position = 0;
+    let mut tick_number = 0u8;
+    loop {
+        for event in event_pump.poll_iter() {
+            screen.handle_event(canvas, &event);
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return None,
+                Event::KeyDown { keycode: Some(Keycode::Return), .. } => {
+                    return Some(String::from_utf8(name.to_vec()).unwrap());
+                }
+                Event::KeyDown { keycode: Some(Keycode::Up), .. } => {
+                    name[position] = if name[position] == b'Z' { b'A' } else { name[position] + 1 };
+                }
+                Event::KeyDown { keycode: Some(Keycode::Down), .. } => {
+                    name[position] = if name[position] == b'A' { b'Z' } else { name[position] - 1 };
+                }
+                Event::KeyDown { keycode: Some(Keycode::Left), .. }
+                | Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => position = position.saturating_sub(1),
+                Event::KeyDown { keycode: Some(Keycode::Right), .. } => position = (position + 1).min(2),
+                Event::KeyDown { keycode: Some(keycode), .. } => {
+                    let key_name = keycode.name();
+                    if let [c @ b'A'..=b'Z'] = key_name.as_bytes() {
+                        name[position] = *c;
+                        position = (position + 1).min(2);
                     }
                 }
+                _ => {}
             }
         }
 
-        for y in 0..level.height {
-            for x in 0..level.width {
-                if let Some(&cell) = level.cell(x, y) {
-                    if let Cell { object: Some(Object::Player), changed_in_current_tick: false, .. } = cell {
-                        if move_left && x >= 1 {
-                            move_player_if_possible(&mut level, x, y, Direction::East);
-                        } else if move_right && x < level.width - 1 {
-                            move_player_if_possible(&mut level, x, y, Direction::West);
-                        } else if move_up && y >= 1 {
-                            move_player_if_possible(&mut level, x, y, Direction::South);
-                        } else if move_down && y < level.height - 1 {
-                            move_player_if_possible(&mut level, x, y, Direction::North);
-                        }
-                    }
-                }
+        screen.clear(canvas);
+        draw_text(canvas, tile_textures, 1, 1, "NEW HIGH SCORE");
+        draw_text(canvas, tile_textures, 1, 3, &score.to_string());
+        for (i, &c) in name.iter().enumerate() {
+            // The letter at the cursor position blinks.
+            if i != position || tick_number % 8 < 4 {
+                draw_text(canvas, tile_textures, 1 + i as u16, 5, &(c as char).to_string());
             }
         }
+        canvas.present();
+        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 15));
+        tick_number = (tick_number + 1) % 8;
+    }
+}
 
-        for y in 0..level.height {
-            for x in 0..level.width {
-                if let Some(&cell) = level.cell(x, y) {
-                    level.set_cell(x, y, Cell { changed_in_current_tick: false, ..cell })
-                }
+
+/// Lets the player choose one of the levels they have already reached.  The
+/// first level is started right away if no other level has been reached yet.
+fn select_level(
+    canvas: &mut WindowCanvas,
+    screen: &mut Screen,
+    event_pump: &mut EventPump,
+    tile_textures: &[(u16, u16, Texture)],
+    campaign: &Campaign,
+) -> Option<usize> {
+    if campaign.reached == 0 {
+        return Some(0);
+    }
+    let mut index = campaign.reached;
+    loop {
+        for event in event_pump.poll_iter() {
+            screen.handle_event(canvas, &event);
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return None,
+                Event::KeyDown { keycode: Some(Keycode::Return), .. } => return Some(index),
+                Event::KeyDown { keycode: Some(Keycode::Left), .. }
+                | Event::KeyDown { keycode: Some(Keycode::Down), .. } => index = index.saturating_sub(1),
+                Event::KeyDown { keycode: Some(Keycode::Right), .. }
+                | Event::KeyDown { keycode: Some(Keycode::Up), .. } => index = (index + 1).min(campaign.reached),
+                _ => {}
             }
         }
 
-        canvas.clear();
-        // Draw background.
-        for y in 0..level.height {
-            for x in 0..level.width {
-                if let Some(cell) = level.cell(x, y) {
-                    if let Some(object) = cell.object {
-                        if object.is_transparent() {
-                            draw_object(&mut canvas, &tile_textures, x, y, None, Object::Empty, tick_number)
-                        }
-                    } else {
-                        draw_object(&mut canvas, &tile_textures, x, y, None, Object::Empty, tick_number);
+        screen.clear(canvas);
+        draw_text(canvas, tile_textures, 1, 1, "SELECT LEVEL");
+        draw_text(canvas, tile_textures, 1, 3, &format!("LEVEL {:03}", campaign.levels[index]));
+        canvas.present();
+        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 15));
+    }
+}
+
+
+/// Lets the player bind every action in turn to a key, a controller button or
+/// a direction of a controller axis, and saves the controls file.  The
+/// previous bindings of the other kind (keyboard or controller) are kept.  The
+/// keys of the screen and the sound cannot be bound.
+fn remap_controls(episode: &str) {
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut controls = load_controls();
+    // Controllers only send events while they are open.
+    let mut input = InputDevices::new(sdl_context.game_controller().unwrap(), controls.clone());
+
+    let (palette, tiles) = load_palette_and_tiles(episode);
+    let (width, height) = logical_size(&tiles);
+    let (mut canvas, mut screen) = Screen::open(&video_subsystem, "controls", width, height);
+    canvas.set_draw_color(Color::RGB(127, 127, 255));
+
+    let texture_creator = canvas.texture_creator();
+    let tile_textures = create_tile_textures(&texture_creator, &tiles, &palette);
+
+    // An axis has to return to the center before it can be bound again.
+    let mut held_axis = None;
+    let mut index = 0;
+    while index < ACTIONS.len() {
+        for event in event_pump.poll_iter() {
+            screen.handle_event(&mut canvas, &event);
+            input.handle_event(&event);
+            let binding = match event {
+                Event::Quit { .. }
+                | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    println!("controls not changed");
+                    return;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F10), .. }
+                | Event::KeyDown { keycode: Some(Keycode::F11), .. } => continue,
+                Event::KeyDown { keycode: Some(keycode), .. } if AUDIO_KEYS.contains(&keycode) => continue,
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => Binding::Key(keycode.name()),
+                Event::ControllerButtonDown { button, .. } => Binding::Button(button.string()),
+                Event::ControllerAxisMotion { axis, value, .. } if value.abs() > AXIS_THRESHOLD => {
+                    if held_axis == Some(axis) {
+                        continue;
                     }
+                    held_axis = Some(axis);
+                    Binding::Axis { name: axis.string(), positive: value > 0 }
                 }
-            }
-        }
-        // Draw (possibly transparent) foreground.
-        for y in 0..level.height {
-            for x in 0..level.width {
-                if let Some(cell) = level.cell(x, y) {
-                    if let Some(object) = cell.object {
-                        draw_object(&mut canvas, &tile_textures, x, y, cell.moving_in_from, object, tick_number);
+                Event::ControllerAxisMotion { axis, .. } => {
+                    if held_axis == Some(axis) {
+                        held_axis = None;
                     }
+                    continue;
                 }
+                _ => continue,
+            };
+            controls.bind(ACTIONS[index], binding);
+            index += 1;
+            if index == ACTIONS.len() {
+                break;
             }
         }
+
+        screen.clear(&mut canvas);
+        if let Some(action) = ACTIONS.get(index) {
+            draw_text(&mut canvas, &tile_textures, 1, 1, "PRESS A KEY OR BUTTON");
+            draw_text(&mut canvas, &tile_textures, 1, 3, &format!("FOR {}", action.name().to_uppercase()));
+        }
         canvas.present();
         ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 15));
-        tick_number = (tick_number + 1) % 12;
     }
+    exit_on_error(controls::save(Path::new(CONTROLS_PATH), &controls));
+    println!("saved {}", CONTROLS_PATH);
 }
 
-fn move_player_if_possible(level: &mut Level, x: u16, y: u16, moving_in_from: Direction) {
-    if let Some(&cell) = level.cell(x, y) {
-        let x_new = (x as i16 + match moving_in_from {
-            Direction::West => 1,
-            Direction::East => -1,
-            _ => 0
-        }) as u16;
-        let y_new = (y as i16 + match moving_in_from {
-            Direction::North => 1,
-            Direction::South => -1,
-            _ => 0
-        }) as u16;
-        if let Some(&neighbor) = level.cell(x_new, y_new) {
-            if neighbor.can_be_entered() {
-                level.set_cell(x, y, Cell {
-                    object: None,
-                    post_occupied: true,
-                    changed_in_current_tick: true,
-                    ..cell
-                });
-                level.set_cell(x_new, y_new, Cell {
-                    object: Some(Object::Player),
-                    pre_occupied: true,
-                    changed_in_current_tick: true,
-                    moving_in_from: Some(moving_in_from),
-                    ..neighbor
-                });
-            }
+
+fn create_tile_textures<'a>(
+    texture_creator: &'a TextureCreator<WindowContext>,
+    tiles: &[Tile],
+    palette: &[Vec<u8>],
+) -> Vec<(u16, u16, Texture<'a>)> {
+    let mut tile_textures = vec![];
+    for tile in tiles {
+        let pixels: Vec<u8> = tile.data.iter().flat_map(|&v| palette[v as usize].clone()).collect();
+        let pixels = &pixels[..];
+        let mut texture: Texture = texture_creator
+            .create_texture_target(PixelFormatEnum::RGBA32, tile.width as u32, tile.height as u32)
+            .unwrap();
+        texture.set_blend_mode(BlendMode::Blend);
+        texture.update(None, pixels, tile.width as usize * 4).unwrap();
+        tile_textures.push((tile.width, tile.height, texture));
+    }
+    tile_textures
+}
+
+
+/// Returns the controls of the controls file without the bindings of the
+/// mute and volume keys, or the default controls if there is none.
+fn load_controls() -> Controls {
+    let controls_path = Path::new(CONTROLS_PATH);
+    if !controls_path.exists() {
+        return Controls::default();
+    }
+    let mut controls = exit_on_error(controls::load(controls_path));
+    let audio_bindings: Vec<Binding> = ACTIONS.iter()
+        .flat_map(|&action| controls.bindings(action))
+        .filter(|binding| match binding {
+            Binding::Key(name) => Keycode::from_name(name).is_some_and(|keycode| AUDIO_KEYS.contains(&keycode)),
+            _ => false,
+        })
+        .cloned()
+        .collect();
+    for binding in &audio_bindings {
+        eprintln!("warning: ignoring binding {:?} of a mute or volume key", binding);
+        controls.unbind(binding);
+    }
+    controls
+}
+
+
+/// Draws the game below the HUD `alpha` of the way from the previous to the
+/// current tick.
+fn draw_game(canvas: &mut WindowCanvas, tile_textures: &[(u16, u16, Texture)], game: &Game, previous: &Level, alpha: f32, camera: &Camera, tick_number: u8) {
+    if game.status == Status::GameOver {
+        draw_text(canvas, tile_textures, 1, 1 + HUD_ROWS, "GAME OVER");
+        return;
+    }
+    // A camera that starts above the level draws it further down; the row of
+    // cells it shows above the view is covered by the HUD.
+    let camera = &Camera { y: camera.y - HUD_ROWS as f32, height: camera.height + HUD_ROWS as f32, ..*camera };
+    draw_level(canvas, tile_textures, &game.level, previous, alpha, camera, tick_number);
+    if let Status::Dying { x, y, ticks_left } = game.status {
+        draw_explosion(canvas, tile_textures, x, y, (-camera.x, -camera.y), DEATH_ANIMATION_TICKS - ticks_left);
+    }
+}
+
+/// Draws frame `frame` of the explosion of the player at (x, y).  A flash is
+/// followed by debris that flies apart in eight directions and cools down from
+/// yellow to dark red.
+fn draw_explosion(canvas: &mut WindowCanvas, tile_textures: &[(u16, u16, Texture)], x: u16, y: u16, offset: (f32, f32), frame: u8) {
+    let (tile_width, tile_height, _) = &tile_textures[BACKGROUND as usize];
+    let (tile_width, tile_height) = (*tile_width as f32, *tile_height as f32);
+    let progress = frame as f32 / DEATH_ANIMATION_TICKS as f32;
+    let center_x = (x as f32 + offset.0 + 0.5) * tile_width;
+    let center_y = (y as f32 + offset.1 + 0.5) * tile_height;
+    let square = |size: f32, dx: f32, dy: f32| {
+        Rect::new(
+            (center_x + dx - size / 2.0).round() as i32,
+            (center_y + dy - size / 2.0).round() as i32,
+            size.round().max(1.0) as u32,
+            size.round().max(1.0) as u32,
+        )
+    };
+
+    let draw_color = canvas.draw_color();
+    if progress < 0.25 {
+        canvas.set_draw_color(Color::RGB(255, 255, 200));
+        canvas.fill_rect(square(tile_width.min(tile_height) * (0.5 + 2.0 * progress), 0.0, 0.0)).unwrap();
+    }
+    canvas.set_draw_color(Color::RGB(255 - (127.0 * progress) as u8, (220.0 * (1.0 - progress)) as u8, 0));
+    let size = tile_width.min(tile_height) / 3.0 * (1.0 - progress);
+    for i in 0..8 {
+        let angle = i as f32 * std::f32::consts::FRAC_PI_4;
+        let distance = 1.5 * progress;
+        canvas.fill_rect(square(size, angle.cos() * distance * tile_width, angle.sin() * distance * tile_height)).unwrap();
+    }
+    canvas.set_draw_color(draw_color);
+}
+
+/// Draws the part of the level that the camera sees, moving objects are drawn
+/// `alpha` of the way from their position in the previous level to their
+/// position in the current level.
+fn draw_level(canvas: &mut WindowCanvas, tile_textures: &[(u16, u16, Texture)], level: &Level, previous: &Level, alpha: f32, camera: &Camera, tick_number: u8) {
+    // Objects moving into view are still in a cell just outside of it.
+    let (columns, rows) = camera.visible_cells(level.width, level.height);
+    let columns = columns.start.saturating_sub(1)..(columns.end + 1).min(level.width);
+    let rows = rows.start.saturating_sub(1)..(rows.end + 1).min(level.height);
+    let visible_cells = || rows.clone().flat_map(|y| columns.clone().filter_map(move |x| level.cell(x, y)));
+
+    // Draw background.
+    for cell in visible_cells().filter(|cell| cell.is_transparent()) {
+        draw_tile(canvas, tile_textures, cell.x, cell.y, (-camera.x, -camera.y), cell.background as usize);
+    }
+    // Draw (possibly transparent) foreground.
+    for cell in visible_cells() {
+        if let Some(object) = cell.object {
+            let (offset_x, offset_y) = interpolation::object_offset(previous, level, cell.x, cell.y, alpha);
+            draw_object(canvas, tile_textures, cell.x, cell.y, (offset_x - camera.x, offset_y - camera.y), object, tick_number);
         }
     }
 }
 
-fn draw_object(mut canvas: &mut WindowCanvas, tile_textures: &Vec<(u16, u16, Texture)>, x: u16, y: u16, offset: Option<Direction>, object: Object, tick_number: u8) {
-    draw_tile(&mut canvas, &tile_textures, x, y, offset, object.tile_number(tick_number) as usize);
+/// Draws the HUD with the score and the state of the game on a black strip at
+/// the top of the window.
+fn draw_hud(canvas: &mut WindowCanvas, tile_textures: &[(u16, u16, Texture)], game: &Game, score: u32) {
+    let (tile_width, tile_height, _) = &tile_textures[BACKGROUND as usize];
+    let draw_color = canvas.draw_color();
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(Rect::new(0, 0, COLUMNS * *tile_width as u32, HUD_ROWS as u32 * *tile_height as u32)).unwrap();
+    canvas.set_draw_color(draw_color);
+    for (x, tile_number) in hud::hud_tiles(game, score).into_iter().enumerate() {
+        if let Some(tile_number) = tile_number {
+            draw_tile(canvas, tile_textures, x as u16, 0, (0.0, 0.0), tile_number as usize);
+        }
+    }
 }
 
-fn draw_tile(canvas: &mut WindowCanvas, tile_textures: &Vec<(u16, u16, Texture)>, x: u16, y: u16, offset: Option<Direction>, tile_number: usize) {
+/// Returns the logical resolution of the game and the editor, which shows
+/// `COLUMNS` x `ROWS` tiles.
+fn logical_size(tiles: &[Tile]) -> (u32, u32) {
+    let tile = &tiles[BACKGROUND as usize];
+    (COLUMNS * tile.width as u32, ROWS * tile.height as u32)
+}
+
+/// Returns a camera that shows as many tiles as fit into the logical
+/// resolution below the HUD.
+fn create_camera(screen: &Screen, tile_textures: &[(u16, u16, Texture)]) -> Camera {
+    let (width, height) = screen.size();
+    let (tile_width, tile_height, _) = &tile_textures[BACKGROUND as usize];
+    Camera::new(width as f32 / *tile_width as f32, height as f32 / *tile_height as f32 - HUD_ROWS as f32)
+}
+
+/// Centers the camera on the player, e.g., when a level starts.
+fn center_camera(camera: &mut Camera, game: &Game) {
+    if let Some((x, y)) = game.player_position() {
+        camera.center_on(x as f32, y as f32, game.level.width, game.level.height);
+    }
+}
+
+/// Lets the camera follow the player as they are drawn `alpha` of the way
+/// from the previous to the current tick.
+fn follow_player(camera: &mut Camera, game: &Game, previous: &Level, alpha: f32) {
+    let target = match game.status {
+        Status::Dying { x, y, .. } => Some((x as f32, y as f32)),
+        _ => game.player_position().map(|(x, y)| {
+            let (offset_x, offset_y) = interpolation::object_offset(previous, &game.level, x, y, alpha);
+            (x as f32 + offset_x, y as f32 + offset_y)
+        }),
+    };
+    if let Some((x, y)) = target {
+        camera.follow(x, y, game.level.width, game.level.height);
+    }
+}
+
+/// Draws a line of text with the letter tiles, starting at the tile position
+/// (x, y).  Characters without a letter tile are left blank.
+fn draw_text(canvas: &mut WindowCanvas, tile_textures: &[(u16, u16, Texture)], x: u16, y: u16, text: &str) {
+    for (i, c) in text.chars().enumerate() {
+        let tile_number = match objects::letter_tile_number(c) {
+            Some(tile_number) => tile_number,
+            None => continue,
+        };
+        draw_tile(canvas, tile_textures, x + i as u16, y, (0.0, 0.0), tile_number as usize);
+    }
+}
+
+fn draw_object(canvas: &mut WindowCanvas, tile_textures: &[(u16, u16, Texture)], x: u16, y: u16, offset: (f32, f32), object: Object, tick_number: u8) {
+    draw_tile(canvas, tile_textures, x, y, offset, object.tile_number(tick_number) as usize);
+}
+
+/// Draws the tile at the tile position (x, y), moved by the offset (in tiles).
+fn draw_tile(canvas: &mut WindowCanvas, tile_textures: &[(u16, u16, Texture)], x: u16, y: u16, offset: (f32, f32), tile_number: usize) {
     let (width, height, tile_texture) = &tile_textures[tile_number];
     canvas.copy(
         tile_texture,
         None,
         Rect::new(
-            x as i32 * *width as i32 + match offset {
-                Some(Direction::West) => -(*width as i32) / 2,
-                Some(Direction::East) => (*width as i32) / 2,
-                _ => 0,
-            },
-            y as i32 * *height as i32 + match offset {
-                Some(Direction::North) => -(*height as i32) / 2,
-                Some(Direction::South) => (*height as i32) / 2,
-                _ => 0,
-            },
+            x as i32 * *width as i32 + (offset.0 * *width as f32).round() as i32,
+            y as i32 * *height as i32 + (offset.1 * *height as f32).round() as i32,
             *width as u32,
             *height as u32,
         ),
@@ -1,78 +1,262 @@
-#[derive(Copy, Clone, Debug)]
-pub enum KeyColor { Yellow, Red, Grey }
+//! The objects in the cells of a level and their tile numbers.
 
-#[derive(Copy, Clone, Debug)]
-pub enum GemKind { Gem1, Gem2, Gem3 }
+use crate::level::Direction;
 
-#[derive(Copy, Clone, Debug)]
+/// The color of a key, a key opens the locks of the same color.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum KeyColor {
+    /// A yellow key or lock.
+    Yellow,
+    /// A red key or lock.
+    Red,
+    /// A grey key or lock.
+    Grey,
+}
+
+/// The kinds of gems, they differ in the points they are worth.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GemKind {
+    /// A gem worth 10 points.
+    Gem1,
+    /// A gem worth 20 points.
+    Gem2,
+    /// A gem worth 50 points.
+    Gem3,
+}
+
+impl GemKind {
+    /// The points the player gets for collecting the gem.
+    pub fn points(self) -> u32 {
+        match self {
+            GemKind::Gem1 => 10,
+            GemKind::Gem2 => 20,
+            GemKind::Gem3 => 50,
+        }
+    }
+}
+
+/// What the player is doing, each action has its own animation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlayerAction {
+    /// Standing still.
+    Idle,
+    /// Walking in the direction.
+    Walking(Direction),
+    /// Snapping an object or breaking a wall without moving.
+    Digging,
+}
+
+/// The order of the walking animations of the player, starting at tile 132.
+const ANIMATION_DIRECTIONS: [Direction; 4] = [Direction::West, Direction::East, Direction::North, Direction::South];
+
+/// The order of the animations of enemies, starting at tile 162.
+const ENEMY_DIRECTIONS: [Direction; 4] = [Direction::North, Direction::East, Direction::South, Direction::West];
+
+/// The object in a cell of a level.  Animated objects keep the frame offset
+/// of the tile they have been loaded from, so that neighboring objects of the
+/// same kind do not necessarily animate in lockstep.
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Object {
-    Dirt { north: bool, east: bool, south: bool, west: bool },
-    Wall { north: bool, east: bool, south: bool, west: bool },
+    /// Dirt and walls have borders towards some of their neighbors.  Level
+    /// files either contain tiles with explicit borders or generic tiles.
+    Dirt {
+        /// The object has a border towards its northern neighbor.
+        north: bool,
+        /// The object has a border towards its eastern neighbor.
+        east: bool,
+        /// The object has a border towards its southern neighbor.
+        south: bool,
+        /// The object has a border towards its western neighbor.
+        west: bool,
+        /// The object has been loaded from a generic tile and is written back
+        /// as one.
+        generic: bool,
+    },
+    /// A wall that only a pickaxe can break.
+    Wall {
+        /// The object has a border towards its northern neighbor.
+        north: bool,
+        /// The object has a border towards its eastern neighbor.
+        east: bool,
+        /// The object has a border towards its southern neighbor.
+        south: bool,
+        /// The object has a border towards its western neighbor.
+        west: bool,
+        /// The object has been loaded from a generic tile and is written back
+        /// as one.
+        generic: bool,
+    },
+    /// An empty cell, only used for the empty tiles of level files.
     Empty,
-    Door { frame_offset: u8 },
+    /// The exit of the level, it opens once all gems have been collected.
+    Door {
+        /// The player can enter the door to complete the level.
+        open: bool,
+        /// The frame the animation is ahead of the animation of other objects
+        /// of the same kind.
+        frame_offset: u8,
+    },
+    /// A brick that only a pickaxe can break.
     Brick,
-    Key { color: KeyColor, frame_offset: u8 },
-    Pickaxe { frame_offset: u8 },
-    Gem { kind: GemKind, frame_offset: u8 },
-    Letter { char: char },
+    /// A key that opens a lock of the same color.
+    Key {
+        /// The color of the key.
+        color: KeyColor,
+        /// The frame the animation is ahead of the animation of other objects
+        /// of the same kind.
+        frame_offset: u8,
+    },
+    /// A pickaxe that breaks bricks and walls.
+    Pickaxe {
+        /// The frame the animation is ahead of the animation of other objects
+        /// of the same kind.
+        frame_offset: u8,
+    },
+    /// A gem, the level is completed once all gems have been collected.
+    Gem {
+        /// The kind of the gem.
+        kind: GemKind,
+        /// The frame the animation is ahead of the animation of other objects
+        /// of the same kind.
+        frame_offset: u8,
+    },
+    /// A letter, digit or punctuation mark, e.g., for the title of a level.
+    Letter {
+        /// The character, one of `LETTERS`.
+        char: char,
+    },
+    /// A boulder that falls down and crushes the player and enemies.
     Boulder,
+    /// A balloon that rises up.
     Balloon,
-    AirshipLeft { frame_offset: u8 },
-    AirshipRight { frame_offset: u8 },
-    Player,
-    Enemy,
-    Lock { color: KeyColor },
+    /// An airship flying to the left.
+    AirshipLeft {
+        /// The frame the animation is ahead of the animation of other objects
+        /// of the same kind.
+        frame_offset: u8,
+    },
+    /// An airship flying to the right.
+    AirshipRight {
+        /// The frame the animation is ahead of the animation of other objects
+        /// of the same kind.
+        frame_offset: u8,
+    },
+    /// The player.
+    Player {
+        /// What the player is doing.
+        action: PlayerAction,
+        /// The frame the animation is ahead of the animation of other objects
+        /// of the same kind.
+        frame_offset: u8,
+    },
+    /// An enemy that kills the player when it catches them.
+    Enemy {
+        /// The direction the enemy is facing.
+        facing: Direction,
+        /// The frame the animation is ahead of the animation of other objects
+        /// of the same kind.
+        frame_offset: u8,
+    },
+    /// A lock that the player opens with a key of the same color.
+    Lock {
+        /// The color of the lock.
+        color: KeyColor,
+    },
+}
+
+/// The characters of the letter tiles 82 to 117, followed by the characters of
+/// tiles 190 and 191.
+pub const LETTERS: &[u8; 38] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.!";
+
+/// Returns the number of the letter tile that shows the character.  Lowercase
+/// letters are shown as uppercase letters.
+pub fn letter_tile_number(c: char) -> Option<u8> {
+    let index = LETTERS.iter().position(|&letter| letter as char == c.to_ascii_uppercase())?;
+    match index {
+        0..=35 => Some(82 + index as u8),
+        _ => Some(190 + index as u8 - 36),
+    }
 }
 
 impl Object {
-    pub fn from_tile_number(sprite_number: u8) -> Result<Object, String> {
+    /// Returns the object a tile of a level file stands for or `None` if the
+    /// tile number is unknown.
+    pub fn from_tile_number(sprite_number: u8) -> Option<Object> {
         match sprite_number {
-            0..=15 | 32 => Ok(Object::Dirt {
+            0..=15 => Some(Object::Dirt {
+                north: sprite_number & 1 != 0,
+                east: sprite_number & 2 != 0,
+                south: sprite_number & 4 != 0,
+                west: sprite_number & 8 != 0,
+                generic: false,
+            }),
+            16..=31 => Some(Object::Wall {
+                north: sprite_number & 1 != 0,
+                east: sprite_number & 2 != 0,
+                south: sprite_number & 4 != 0,
+                west: sprite_number & 8 != 0,
+                generic: false,
+            }),
+            32 => Some(Object::Dirt {
                 north: false,
                 east: false,
                 south: false,
                 west: false,
+                generic: true,
             }),
-            16..=31 | 33 => Ok(Object::Wall {
+            33 => Some(Object::Wall {
                 north: false,
                 east: false,
                 south: false,
                 west: false,
+                generic: true,
             }),
-            34 | 189 => Ok(Object::Empty),
-            35..=38 => Ok(Object::Door { frame_offset: 0 }),
-            39 => Ok(Object::Brick),
-            40..=45 => Ok(Object::Key { color: KeyColor::Yellow, frame_offset: sprite_number - 40 }),
-            46..=51 => Ok(Object::Key { color: KeyColor::Red, frame_offset: sprite_number - 46 }),
-            52..=57 => Ok(Object::Key { color: KeyColor::Grey, frame_offset: sprite_number - 52 }),
-            58..=63 => Ok(Object::Pickaxe { frame_offset: sprite_number - 58 }),
-            64..=69 => Ok(Object::Gem { kind: GemKind::Gem1, frame_offset: sprite_number - 64 }),
-            70..=75 => Ok(Object::Gem { kind: GemKind::Gem2, frame_offset: sprite_number - 70 }),
-            76..=81 => Ok(Object::Gem { kind: GemKind::Gem3, frame_offset: sprite_number - 76 }),
-            82..=117 | 190 | 191 => Ok(Object::Letter { char: 'a' }),
-            118 => Ok(Object::Boulder),
-            119 => Ok(Object::Balloon),
-            120..=125 => Ok(Object::AirshipLeft { frame_offset: sprite_number - 120 }),
-            126..=131 => Ok(Object::AirshipRight { frame_offset: sprite_number - 126 }),
-            132..=161 => Ok(Object::Player),
-            162..=185 => Ok(Object::Enemy),
-            186 => Ok(Object::Lock { color: KeyColor::Yellow }),
-            187 => Ok(Object::Lock { color: KeyColor::Red }),
-            188 => Ok(Object::Lock { color: KeyColor::Grey }),
-            _ => Err(format!("invalid sprite number {}", sprite_number)),
+            34 | 189 => Some(Object::Empty),
+            35..=38 => Some(Object::Door { open: false, frame_offset: sprite_number - 35 }),
+            39 => Some(Object::Brick),
+            40..=45 => Some(Object::Key { color: KeyColor::Yellow, frame_offset: sprite_number - 40 }),
+            46..=51 => Some(Object::Key { color: KeyColor::Red, frame_offset: sprite_number - 46 }),
+            52..=57 => Some(Object::Key { color: KeyColor::Grey, frame_offset: sprite_number - 52 }),
+            58..=63 => Some(Object::Pickaxe { frame_offset: sprite_number - 58 }),
+            64..=69 => Some(Object::Gem { kind: GemKind::Gem1, frame_offset: sprite_number - 64 }),
+            70..=75 => Some(Object::Gem { kind: GemKind::Gem2, frame_offset: sprite_number - 70 }),
+            76..=81 => Some(Object::Gem { kind: GemKind::Gem3, frame_offset: sprite_number - 76 }),
+            82..=117 => Some(Object::Letter { char: LETTERS[sprite_number as usize - 82] as char }),
+            190 | 191 => Some(Object::Letter { char: LETTERS[sprite_number as usize - 190 + 36] as char }),
+            118 => Some(Object::Boulder),
+            119 => Some(Object::Balloon),
+            120..=125 => Some(Object::AirshipLeft { frame_offset: sprite_number - 120 }),
+            126..=131 => Some(Object::AirshipRight { frame_offset: sprite_number - 126 }),
+            132..=155 => Some(Object::Player {
+                action: PlayerAction::Walking(ANIMATION_DIRECTIONS[(sprite_number as usize - 132) / 6]),
+                frame_offset: (sprite_number - 132) % 6,
+            }),
+            156 => Some(Object::Player { action: PlayerAction::Idle, frame_offset: 0 }),
+            157..=161 => Some(Object::Player { action: PlayerAction::Digging, frame_offset: sprite_number - 157 }),
+            162..=185 => Some(Object::Enemy {
+                facing: ENEMY_DIRECTIONS[(sprite_number as usize - 162) / 6],
+                frame_offset: (sprite_number - 162) % 6,
+            }),
+            186 => Some(Object::Lock { color: KeyColor::Yellow }),
+            187 => Some(Object::Lock { color: KeyColor::Red }),
+            188 => Some(Object::Lock { color: KeyColor::Grey }),
+            _ => None,
         }
     }
 
+    /// Returns the tile that shows the object in the frame of its animation
+    /// at `tick_number`.
     pub fn tile_number(self, tick_number: u8) -> u8 {
         match self {
-            Object::Dirt { north, east, south, west } => {
-                0 | north as u8 | (east as u8) << 1 | (south as u8) << 2 | (west as u8) << 3
+            Object::Dirt { north, east, south, west, .. } => {
+                north as u8 | (east as u8) << 1 | (south as u8) << 2 | (west as u8) << 3
             }
-            Object::Wall { north, east, south, west } => {
+            Object::Wall { north, east, south, west, .. } => {
                 16 | north as u8 | (east as u8) << 1 | (south as u8) << 2 | (west as u8) << 3
             }
             Object::Empty => 34,
-            Object::Door { .. } => 35,  // TODO unlocked door
+            Object::Door { open: false, .. } => 35,
+            Object::Door { open: true, frame_offset } => 35 + (frame_offset + tick_number) % 4,
             Object::Brick => 39,
             Object::Key { color, frame_offset } => match color {
                 KeyColor::Yellow => 40 + (frame_offset + tick_number) % 6,
@@ -85,13 +269,23 @@ impl Object {
                 GemKind::Gem2 => 70 + (frame_offset + tick_number) % 6,
                 GemKind::Gem3 => 76 + (frame_offset + tick_number) % 6,
             },
-            Object::Letter { .. } => 82,  // TODO char
+            Object::Letter { char } => letter_tile_number(char).unwrap_or(34),
             Object::Boulder => 118,
             Object::Balloon => 119,
             Object::AirshipLeft { frame_offset } => 120 + (frame_offset + tick_number) % 6,
             Object::AirshipRight { frame_offset } => 126 + (frame_offset + tick_number) % 6,
-            Object::Player => 156,  // TODO animation
-            Object::Enemy => 180,  // TODO animation
+            Object::Player { action, frame_offset } => match action {
+                PlayerAction::Idle => 156,
+                PlayerAction::Walking(direction) => {
+                    let index = ANIMATION_DIRECTIONS.iter().position(|&d| d == direction).unwrap() as u8;
+                    132 + 6 * index + (frame_offset + tick_number) % 6
+                }
+                PlayerAction::Digging => 157 + (frame_offset + tick_number) % 5,
+            },
+            Object::Enemy { facing, frame_offset } => {
+                let index = ENEMY_DIRECTIONS.iter().position(|&d| d == facing).unwrap() as u8;
+                162 + 6 * index + (frame_offset + tick_number) % 6
+            }
             Object::Lock { color } => match color {
                 KeyColor::Yellow => 186,
                 KeyColor::Red => 187,
@@ -100,28 +294,131 @@ impl Object {
         }
     }
 
-    pub fn is_transparent(self) -> bool {
+    /// Returns the tile number that represents the object in a level file,
+    /// i.e., the inverse of `from_tile_number`.
+    pub fn to_tile_number(self) -> u8 {
         match self {
+            Object::Dirt { generic: true, .. } => 32,
+            Object::Wall { generic: true, .. } => 33,
+            Object::Door { frame_offset, .. } => 35 + frame_offset,
+            object => object.tile_number(0),
+        }
+    }
+
+    /// Whether the background shows through the object.
+    pub fn is_transparent(self) -> bool {
+        matches!(
+            self,
             Object::Gem { .. }
             | Object::Boulder
             | Object::Balloon
             | Object::AirshipLeft { .. }
             | Object::AirshipRight { .. }
-            | Object::Player
-            | Object::Enemy => true,
-            _ => false,
-        }
+            | Object::Player { .. }
+            | Object::Enemy { .. }
+        )
     }
 
+    /// Whether the player can enter the cell of the object, usually to collect
+    /// it.
     pub fn can_be_entered(self) -> bool {
-        match self {
+        matches!(
+            self,
             Object::Empty
             | Object::Dirt { .. }
+            | Object::Door { open: true, .. }
             | Object::Key { .. }
             | Object::Pickaxe { .. }
             | Object::Gem { .. }
-            | Object::Letter { .. } => true,
-            _ => false,
+            | Object::Letter { .. }
+        )
+    }
+
+    /// Objects affected by gravity fall down into empty cells.
+    pub fn falls(self) -> bool {
+        matches!(self, Object::Boulder | Object::Gem { .. })
+    }
+
+    /// Objects that rise up into empty cells, i.e., the opposite of falling.
+    pub fn rises(self) -> bool {
+        matches!(self, Object::Balloon)
+    }
+
+    /// Objects that the player can push to the side if the cell behind them is
+    /// empty.
+    pub fn can_be_pushed(self) -> bool {
+        matches!(self, Object::Balloon | Object::Boulder)
+    }
+
+    /// The number of ticks the player has to push against an object before it
+    /// moves.
+    pub fn push_resistance(self) -> u8 {
+        match self {
+            Object::Boulder => 2,
+            _ => 0,
+        }
+    }
+
+    /// Returns the airship flying in the opposite direction or the object
+    /// itself if it is not an airship.
+    pub fn turned_around(self) -> Object {
+        match self {
+            Object::AirshipLeft { frame_offset } => Object::AirshipRight { frame_offset },
+            Object::AirshipRight { frame_offset } => Object::AirshipLeft { frame_offset },
+            object => object,
+        }
+    }
+
+    /// Falling objects roll off rounded objects to the side if possible.
+    pub fn is_rounded(self) -> bool {
+        matches!(self, Object::Boulder | Object::Gem { .. } | Object::Brick)
+    }
+
+    /// Objects that are killed when a falling object lands on them.
+    pub fn can_be_crushed(self) -> bool {
+        matches!(self, Object::Player { .. } | Object::Enemy { .. })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_letters() {
+        assert_eq!(Object::from_tile_number(82), Some(Object::Letter { char: 'A' }));
+        assert_eq!(Object::from_tile_number(107), Some(Object::Letter { char: 'Z' }));
+        assert_eq!(Object::from_tile_number(108), Some(Object::Letter { char: '0' }));
+        assert_eq!(Object::from_tile_number(191), Some(Object::Letter { char: '!' }));
+        assert_eq!(letter_tile_number('q'), Some(98));
+        assert_eq!(letter_tile_number('.'), Some(190));
+        assert_eq!(letter_tile_number('#'), None);
+        for tile_number in (82..=117).chain(190..=191) {
+            assert_eq!(Object::from_tile_number(tile_number).unwrap().to_tile_number(), tile_number);
+        }
+    }
+
+    #[test]
+    fn test_animations() {
+        assert_eq!(
+            Object::from_tile_number(145),
+            Some(Object::Player { action: PlayerAction::Walking(Direction::North), frame_offset: 1 }),
+        );
+        assert_eq!(Object::from_tile_number(159), Some(Object::Player { action: PlayerAction::Digging, frame_offset: 2 }));
+        assert_eq!(Object::from_tile_number(171), Some(Object::Enemy { facing: Direction::East, frame_offset: 3 }));
+
+        let player = Object::Player { action: PlayerAction::Walking(Direction::East), frame_offset: 0 };
+        assert_eq!((0..7).map(|tick| player.tile_number(tick)).collect::<Vec<_>>(), vec![138, 139, 140, 141, 142, 143, 138]);
+        let player = Object::Player { action: PlayerAction::Digging, frame_offset: 4 };
+        assert_eq!(player.tile_number(1), 157);
+        let player = Object::Player { action: PlayerAction::Idle, frame_offset: 0 };
+        assert_eq!(player.tile_number(5), 156);
+        let enemy = Object::Enemy { facing: Direction::South, frame_offset: 0 };
+        assert_eq!(enemy.tile_number(8), 176);
+
+        for tile_number in 132..=185 {
+            assert_eq!(Object::from_tile_number(tile_number).unwrap().to_tile_number(), tile_number);
         }
     }
 }
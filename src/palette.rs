@@ -1,21 +1,171 @@
-use std::fs::File;
-use std::io::Read;
+//! Palette files (`.PAL`) with 6-bit VGA colors and the import of GIMP and
+//! JASC palettes.
+
+use std::fs;
 use std::path::Path;
 
-pub fn load_palette(path: &Path) -> Result<Vec<Vec<u8>>, String> {
-    let mut file = File::open(path).unwrap();
-    let mut buffer = vec!();
-    file.read_to_end(&mut buffer).unwrap();
+use crate::error::Error;
+
+/// Reads a palette file of 6-bit RGB values and returns them as 8-bit RGBA
+/// colors, index 0 is transparent.
+pub fn load_palette(path: &Path) -> Result<Vec<Vec<u8>>, Error> {
+    let buffer = match fs::read(path) {
+        Ok(buffer) => buffer,
+        Err(reason) => return Err(Error::io(path, reason)),
+    };
     if buffer.len() % 3 != 0 {
-        return Err(format!(
-            "palette file {} is corrupted: length has to be divisible by 3, was {}",
-            path.display(), buffer.len(),
-        ));
+        return Err(Error::InvalidPaletteLength { path: path.into(), length: buffer.len() });
     }
-    Ok(buffer.iter().map(|v| 4 * v).collect::<Vec<u8>>()
-        .chunks_exact(3).enumerate().map(
+    if let Some(offset) = buffer.iter().position(|&v| v > 63) {
+        return Err(Error::InvalidPaletteValue { path: path.into(), offset, value: buffer[offset] });
+    }
+    Ok(add_alpha(buffer.iter().map(|v| 4 * v).collect::<Vec<u8>>()))
+}
+
+
+/// Turns RGB values into RGBA colors, index 0 is transparent.
+fn add_alpha(rgb: Vec<u8>) -> Vec<Vec<u8>> {
+    rgb.chunks_exact(3).enumerate().map(
         |(i, v)| {
             [v, &[if i == 0 { 0 } else { 255 }]].concat()
         }
-    ).collect())
+    ).collect()
+}
+
+
+/// Converts the 8-bit colors back to the 6-bit VGA values of the palette file.
+fn serialize_palette(palette: &[Vec<u8>]) -> Vec<u8> {
+    palette.iter()
+        .flat_map(|color| color[..3].iter().map(|&v| ((v as u16 + 2) / 4).min(63) as u8))
+        .collect()
+}
+
+
+/// Writes the colors as a palette file, dropping the alpha values.
+pub fn save_palette(path: &Path, palette: &[Vec<u8>]) -> Result<(), Error> {
+    fs::write(path, serialize_palette(palette)).map_err(|reason| Error::io(path, reason))
+}
+
+
+/// Reads a GIMP (`.gpl`) or JASC (`.pal`) palette, the format is detected by
+/// the first line.
+pub fn import_palette(path: &Path) -> Result<Vec<Vec<u8>>, Error> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(reason) => return Err(Error::io(path, reason)),
+    };
+    let rgb = match content.lines().next().map(str::trim) {
+        Some("GIMP Palette") => parse_gpl(path, &content)?,
+        Some("JASC-PAL") => parse_jasc(path, &content)?,
+        _ => return Err(Error::syntax(path, 1, "unknown palette format")),
+    };
+    Ok(add_alpha(rgb))
+}
+
+
+/// A GIMP palette has a header of `Name:` and `Columns:` lines and comments,
+/// followed by one `R G B name` line per color.
+fn parse_gpl(path: &Path, content: &str) -> Result<Vec<u8>, Error> {
+    let mut rgb = vec![];
+    for (line_number, line) in content.lines().enumerate().skip(1).map(|(i, line)| (i + 1, line.trim())) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+        rgb.extend(parse_color(path, line.split_whitespace().take(3), line_number)?);
+    }
+    Ok(rgb)
+}
+
+
+/// A JASC palette starts with the lines `JASC-PAL`, `0100` and the number of
+/// colors, followed by one `R G B` line per color.
+fn parse_jasc(path: &Path, content: &str) -> Result<Vec<u8>, Error> {
+    let mut lines = content.lines().map(str::trim);
+    lines.next();
+    if lines.next() != Some("0100") {
+        return Err(Error::syntax(path, 2, "unsupported JASC version"));
+    }
+    let count: usize = match lines.next().map(str::parse) {
+        Some(Ok(count)) => count,
+        _ => return Err(Error::syntax(path, 3, "invalid number of colors")),
+    };
+    let mut rgb = vec![];
+    let mut last_line_number = 3;
+    for (line_number, line) in lines.enumerate().map(|(i, line)| (i + 4, line)) {
+        last_line_number = line_number;
+        if line.is_empty() {
+            continue;
+        }
+        rgb.extend(parse_color(path, line.split_whitespace(), line_number)?);
+    }
+    if rgb.len() != 3 * count {
+        let message = format!("expected {} colors, found {}", count, rgb.len() / 3);
+        return Err(Error::syntax(path, last_line_number, &message));
+    }
+    Ok(rgb)
+}
+
+
+fn parse_color<'a>(path: &Path, values: impl Iterator<Item = &'a str>, line_number: usize) -> Result<Vec<u8>, Error> {
+    let color = values.map(str::parse).collect::<Result<Vec<u8>, _>>();
+    match color {
+        Ok(color) if color.len() == 3 => Ok(color),
+        _ => Err(Error::syntax(path, line_number, "invalid color")),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_save() {
+        let path = NamedTempFile::new().unwrap().into_temp_path();
+        fs::write(&path, [0, 1, 2, 63, 62, 61]).unwrap();
+        let palette = load_palette(&path).unwrap();
+        assert_eq!(palette, vec![vec![0, 4, 8, 0], vec![252, 248, 244, 255]]);
+
+        save_palette(&path, &palette).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![0, 1, 2, 63, 62, 61]);
+
+        // 8-bit values are rounded to the nearest 6-bit value
+        save_palette(&path, &[vec![255, 1, 2, 255], vec![130, 129, 128, 255]]).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![63, 0, 1, 33, 32, 32]);
+    }
+
+    #[test]
+    fn test_load_corrupted() {
+        let path = NamedTempFile::new().unwrap().into_temp_path();
+        fs::write(&path, [0, 1, 2, 3]).unwrap();
+        assert!(matches!(load_palette(&path), Err(Error::InvalidPaletteLength { length: 4, .. })));
+
+        fs::write(&path, [0, 1, 2, 3, 64, 5]).unwrap();
+        assert!(matches!(load_palette(&path), Err(Error::InvalidPaletteValue { offset: 4, value: 64, .. })));
+    }
+
+    #[test]
+    fn test_import_gpl() {
+        let path = NamedTempFile::new().unwrap().into_temp_path();
+        fs::write(&path, "GIMP Palette\nName: Mining\nColumns: 16\n# comment\n  0   0   0\tBlack\n255 128 4 Orange\n").unwrap();
+        assert_eq!(import_palette(&path).unwrap(), vec![vec![0, 0, 0, 0], vec![255, 128, 4, 255]]);
+
+        fs::write(&path, "GIMP Palette\n255 128\n").unwrap();
+        assert_eq!(import_palette(&path).unwrap_err().to_string(), format!("{}:2: invalid color", path.display()));
+    }
+
+    #[test]
+    fn test_import_jasc() {
+        let path = NamedTempFile::new().unwrap().into_temp_path();
+        fs::write(&path, "JASC-PAL\r\n0100\r\n2\r\n0 0 0\r\n255 128 4\r\n").unwrap();
+        assert_eq!(import_palette(&path).unwrap(), vec![vec![0, 0, 0, 0], vec![255, 128, 4, 255]]);
+
+        fs::write(&path, "JASC-PAL\n0100\n3\n0 0 0\n").unwrap();
+        assert!(matches!(import_palette(&path), Err(Error::Syntax { line: 4, message, .. }) if message == "expected 3 colors, found 1"));
+
+        fs::write(&path, "RIFF").unwrap();
+        assert!(matches!(import_palette(&path), Err(Error::Syntax { line: 1, message, .. }) if message == "unknown palette format"));
+    }
 }
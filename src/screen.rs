@@ -0,0 +1,100 @@
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::video::FullscreenType;
+use sdl2::VideoSubsystem;
+
+use cm_rust::display::{Scaling, Viewport};
+
+/// The window is opened at the largest integer scale that fits into this size.
+const WINDOW_SIZE: (u32, u32) = (1200, 800);
+
+/// Everything is drawn at a logical resolution that is scaled to the window
+/// and centered between black bars.  `F11` toggles fullscreen and `F10`
+/// switches between integer and fractional scaling.
+pub struct Screen {
+    width: u32,
+    height: u32,
+    scaling: Scaling,
+    viewport: Viewport,
+}
+
+impl Screen {
+    /// Opens a resizable window for a logical resolution of `width` x `height`
+    /// pixels.
+    pub fn open(video_subsystem: &VideoSubsystem, title: &str, width: u32, height: u32) -> (WindowCanvas, Screen) {
+        let scale = (WINDOW_SIZE.0 / width).min(WINDOW_SIZE.1 / height).max(1);
+        let window = video_subsystem.window(title, width * scale, height * scale)
+            .position_centered()
+            .resizable()
+            .build()
+            .unwrap();
+
+        let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+        let mut screen = Screen {
+            width,
+            height,
+            scaling: Scaling::Integer,
+            viewport: Viewport::fit((width, height), (width, height), Scaling::Integer),
+        };
+        screen.update(&mut canvas);
+        (canvas, screen)
+    }
+
+    /// The logical resolution in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Handles the keys of the screen and changes of the window size.
+    pub fn handle_event(&mut self, canvas: &mut WindowCanvas, event: &Event) {
+        match event {
+            Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
+                let fullscreen = match canvas.window().fullscreen_state() {
+                    FullscreenType::Off => FullscreenType::Desktop,
+                    _ => FullscreenType::Off,
+                };
+                canvas.window_mut().set_fullscreen(fullscreen).unwrap();
+                self.update(canvas);
+            }
+            Event::KeyDown { keycode: Some(Keycode::F10), .. } => {
+                self.scaling = match self.scaling {
+                    Scaling::Integer => Scaling::Fractional,
+                    Scaling::Fractional => Scaling::Integer,
+                };
+                self.update(canvas);
+            }
+            Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => self.update(canvas),
+            _ => {}
+        }
+    }
+
+    /// Converts a position in the window, e.g., of the mouse, to logical
+    /// pixels.
+    pub fn to_logical(&self, x: i32, y: i32) -> (i32, i32) {
+        self.viewport.to_logical(x, y)
+    }
+
+    /// Clears the bars to black and the logical resolution to the draw color.
+    pub fn clear(&self, canvas: &mut WindowCanvas) {
+        let draw_color = canvas.draw_color();
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas.set_draw_color(draw_color);
+        canvas.fill_rect(Rect::new(0, 0, self.width, self.height)).unwrap();
+    }
+
+    /// Fits the logical resolution into the current size of the window.
+    fn update(&mut self, canvas: &mut WindowCanvas) {
+        let window_size = canvas.output_size().unwrap();
+        self.viewport = Viewport::fit((self.width, self.height), window_size, self.scaling);
+        // The viewport is given in window pixels, the clip rectangle in
+        // logical pixels.
+        canvas.set_scale(1.0, 1.0).unwrap();
+        canvas.set_viewport(Rect::new(self.viewport.x, self.viewport.y, self.viewport.width, self.viewport.height));
+        canvas.set_scale(self.viewport.scale, self.viewport.scale).unwrap();
+        canvas.set_clip_rect(Rect::new(0, 0, self.width, self.height));
+    }
+}
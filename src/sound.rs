@@ -0,0 +1,243 @@
+//! Synthesized sound effects and music, and mixing them into the samples of an
+//! audio device.  The frontend only has to pass the samples on to SDL.
+
+use crate::game::GameEvent;
+
+/// A sound effect.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sound {
+    /// Digging through dirt or breaking an object.
+    Dig,
+    /// Collecting a gem.
+    Gem,
+    /// A falling object landing.
+    Landed,
+    /// Picking up a key or a pickaxe.
+    Key,
+    /// Opening the doors or a lock.
+    Door,
+    /// The death of the player.
+    Death,
+    /// Completing the level.
+    LevelCompleted,
+}
+
+const SOUNDS: [Sound; 7] = [
+    Sound::Dig, Sound::Gem, Sound::Landed, Sound::Key, Sound::Door, Sound::Death, Sound::LevelCompleted,
+];
+
+/// The loudest volume, volumes range from 0 to this value.
+pub const MAX_VOLUME: u8 = 10;
+
+/// At most this many effects are played at the same time, a new effect stops
+/// the oldest one.
+const MAX_CHANNELS: usize = 8;
+
+/// Returns the effect that is played when the event happens.
+pub fn sound_for_event(event: GameEvent) -> Option<Sound> {
+    match event {
+        GameEvent::Dug | GameEvent::Broken { .. } => Some(Sound::Dig),
+        GameEvent::GemCollected { .. } => Some(Sound::Gem),
+        GameEvent::Landed { .. } => Some(Sound::Landed),
+        GameEvent::KeyCollected { .. } | GameEvent::PickaxeCollected => Some(Sound::Key),
+        GameEvent::DoorOpened | GameEvent::LockOpened { .. } => Some(Sound::Door),
+        GameEvent::PlayerDied => Some(Sound::Death),
+        GameEvent::LevelCompleted => Some(Sound::LevelCompleted),
+        GameEvent::Exploded | GameEvent::EnemyKilled => None,
+    }
+}
+
+
+#[derive(Clone, Copy)]
+enum Wave {
+    Square,
+    /// White noise that changes its value at the frequency.
+    Noise,
+}
+
+/// A note of `frequency` Hz (0 is a rest) lasting `duration` milliseconds.
+type Note = (Wave, f32, u32);
+
+fn notes(sound: Sound) -> &'static [Note] {
+    use self::Wave::*;
+    match sound {
+        Sound::Dig => &[(Noise, 4000.0, 60)],
+        Sound::Gem => &[(Square, 880.0, 50), (Square, 1320.0, 80)],
+        Sound::Landed => &[(Noise, 500.0, 120)],
+        Sound::Key => &[(Square, 660.0, 60), (Square, 990.0, 60)],
+        Sound::Door => &[(Square, 220.0, 100), (Square, 330.0, 100), (Square, 440.0, 150)],
+        Sound::Death => &[(Square, 440.0, 120), (Square, 330.0, 120), (Square, 220.0, 240)],
+        Sound::LevelCompleted => &[(Square, 523.0, 100), (Square, 659.0, 100), (Square, 784.0, 100), (Square, 1047.0, 250)],
+    }
+}
+
+/// The music loop, a short tune in A minor.
+const MUSIC: &[Note] = &[
+    (Wave::Square, 220.0, 300), (Wave::Square, 262.0, 300), (Wave::Square, 330.0, 300), (Wave::Square, 262.0, 300),
+    (Wave::Square, 294.0, 300), (Wave::Square, 349.0, 300), (Wave::Square, 330.0, 600),
+    (Wave::Square, 220.0, 300), (Wave::Square, 262.0, 300), (Wave::Square, 330.0, 300), (Wave::Square, 392.0, 300),
+    (Wave::Square, 349.0, 300), (Wave::Square, 330.0, 300), (Wave::Square, 294.0, 300), (Wave::Square, 0.0, 300),
+];
+
+/// Renders the notes as samples from -1 to 1.  Every note fades out to avoid
+/// clicks between notes.
+fn synthesize(notes: &[Note], sample_rate: u32) -> Vec<f32> {
+    let mut samples = vec![];
+    let mut noise_state = 0x1234_5678u32;
+    let mut noise_value = 0.0;
+    for &(wave, frequency, duration) in notes {
+        let length = (sample_rate as u64 * duration as u64 / 1000) as usize;
+        let period = if frequency > 0.0 { sample_rate as f32 / frequency } else { 0.0 };
+        for i in 0..length {
+            let value = if period == 0.0 {
+                0.0
+            } else {
+                match wave {
+                    Wave::Square => if (i as f32 % period) < period / 2.0 { 1.0 } else { -1.0 },
+                    Wave::Noise => {
+                        if i as f32 % period < 1.0 {
+                            // xorshift keeps the effects the same every time
+                            noise_state ^= noise_state << 13;
+                            noise_state ^= noise_state >> 17;
+                            noise_state ^= noise_state << 5;
+                            noise_value = noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0;
+                        }
+                        noise_value
+                    }
+                }
+            };
+            samples.push(value * (1.0 - i as f32 / length as f32));
+        }
+    }
+    samples
+}
+
+
+/// Mixes the effects that are currently playing and the music loop.
+pub struct Mixer {
+    sounds: Vec<Vec<f32>>,
+    /// The effects that are playing, with the position of their next sample.
+    channels: Vec<(Sound, usize)>,
+    music: Vec<f32>,
+    music_position: usize,
+    /// The music loop is mixed in.
+    pub music_playing: bool,
+    volume: u8,
+    muted: bool,
+}
+
+impl Mixer {
+    /// Synthesizes the effects and the music at `sample_rate` samples per
+    /// second.
+    pub fn new(sample_rate: u32) -> Mixer {
+        Mixer {
+            sounds: SOUNDS.iter().map(|&sound| synthesize(notes(sound), sample_rate)).collect(),
+            channels: vec![],
+            music: synthesize(MUSIC, sample_rate),
+            music_position: 0,
+            music_playing: false,
+            volume: MAX_VOLUME / 2,
+            muted: false,
+        }
+    }
+
+    /// Starts playing the effect.
+    pub fn play(&mut self, sound: Sound) {
+        if self.channels.len() == MAX_CHANNELS {
+            self.channels.remove(0);
+        }
+        self.channels.push((sound, 0));
+    }
+
+    /// The volume from 0 to `MAX_VOLUME`.
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Changes the volume by `change` steps, unmuting the sound.
+    pub fn change_volume(&mut self, change: i8) {
+        self.volume = (self.volume as i8 + change).clamp(0, MAX_VOLUME as i8) as u8;
+        self.muted = false;
+    }
+
+    /// Whether the sound is muted, independent of the volume.
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Mutes or unmutes the sound.
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    /// Fills the buffer with the next samples and removes finished effects.
+    pub fn mix(&mut self, out: &mut [f32]) {
+        let volume = if self.muted { 0.0 } else { self.volume as f32 / MAX_VOLUME as f32 };
+        for sample in out.iter_mut() {
+            let mut value = 0.0;
+            for (sound, position) in &mut self.channels {
+                if let Some(v) = self.sounds[*sound as usize].get(*position) {
+                    value += v * 0.25;
+                    *position += 1;
+                }
+            }
+            if self.music_playing && !self.music.is_empty() {
+                value += self.music[self.music_position] * 0.08;
+                self.music_position = (self.music_position + 1) % self.music.len();
+            }
+            *sample = (value * volume).clamp(-1.0, 1.0);
+        }
+        let sounds = &self.sounds;
+        self.channels.retain(|(sound, position)| *position < sounds[*sound as usize].len());
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::objects::GemKind;
+
+    use super::*;
+
+    #[test]
+    fn test_sound_for_event() {
+        assert_eq!(sound_for_event(GameEvent::Dug), Some(Sound::Dig));
+        assert_eq!(sound_for_event(GameEvent::GemCollected { kind: GemKind::Gem2 }), Some(Sound::Gem));
+        assert_eq!(sound_for_event(GameEvent::PlayerDied), Some(Sound::Death));
+        assert_eq!(sound_for_event(GameEvent::EnemyKilled), None);
+    }
+
+    #[test]
+    fn test_synthesize() {
+        let samples = synthesize(&[(Wave::Square, 1000.0, 10), (Wave::Noise, 0.0, 5)], 8000);
+        assert_eq!(samples.len(), 80 + 40);
+        let signs: Vec<f32> = samples[..8].iter().map(|sample| sample.signum()).collect();
+        assert_eq!(signs, vec![1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+        assert_eq!(samples[0], 1.0);
+        assert!(samples[79].abs() < 0.02);
+        assert!(samples[80..].iter().all(|&sample| sample == 0.0));
+        assert_eq!(synthesize(notes(Sound::Dig), 8000), synthesize(notes(Sound::Dig), 8000));
+    }
+
+    #[test]
+    fn test_mix() {
+        let mut mixer = Mixer::new(8000);
+        let mut out = vec![1.0; 100];
+        mixer.mix(&mut out);
+        assert!(out.iter().all(|&sample| sample == 0.0));
+
+        mixer.play(Sound::Gem);
+        mixer.mix(&mut out);
+        assert_eq!(out[0], 0.25 * 0.5);
+        mixer.toggle_mute();
+        mixer.mix(&mut out);
+        assert!(out.iter().all(|&sample| sample == 0.0));
+        mixer.change_volume(20);
+        assert_eq!((mixer.volume(), mixer.is_muted()), (MAX_VOLUME, false));
+
+        // finished effects are removed
+        let mut out = vec![0.0; 8000];
+        mixer.mix(&mut out);
+        assert!(mixer.channels.is_empty());
+    }
+}
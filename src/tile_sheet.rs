@@ -0,0 +1,276 @@
+//! Exporting the tiles to a PNG sprite sheet and importing them back.
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::tiles::Tile;
+
+/// The number of tiles in a row of the sprite sheet.
+const COLUMNS: usize = 16;
+
+/// The position and size of a tile in the sprite sheet.
+#[derive(Debug, PartialEq)]
+pub struct TileEntry {
+    /// The left edge in pixels.
+    pub x: u32,
+    /// The top edge in pixels.
+    pub y: u32,
+    /// The width in pixels.
+    pub width: u16,
+    /// The height in pixels.
+    pub height: u16,
+}
+
+
+/// Arranges the tiles in rows of 16 tiles, each cell as large as the largest
+/// tile.  Returns the entries and the size of the sheet.
+fn layout(tiles: &[Tile]) -> (Vec<TileEntry>, u32, u32) {
+    let cell_width = tiles.iter().map(|tile| tile.width as u32).max().unwrap_or(0);
+    let cell_height = tiles.iter().map(|tile| tile.height as u32).max().unwrap_or(0);
+    let entries = tiles.iter().enumerate().map(|(i, tile)| TileEntry {
+        x: (i % COLUMNS) as u32 * cell_width,
+        y: (i / COLUMNS) as u32 * cell_height,
+        width: tile.width,
+        height: tile.height,
+    }).collect();
+    let rows = tiles.len().div_ceil(COLUMNS);
+    (entries, COLUMNS.min(tiles.len()) as u32 * cell_width, rows as u32 * cell_height)
+}
+
+
+/// The index is a TOML file with a `[[tiles]]` table for every tile.
+fn serialize_index(entries: &[TileEntry]) -> String {
+    let mut index = String::new();
+    for entry in entries {
+        index.push_str(&format!(
+            "[[tiles]]\nx = {}\ny = {}\nwidth = {}\nheight = {}\n\n",
+            entry.x, entry.y, entry.width, entry.height,
+        ));
+    }
+    index
+}
+
+
+fn parse_index(path: &Path, index: &str) -> Result<Vec<TileEntry>, Error> {
+    let mut entries = vec![];
+    let mut values: Vec<Option<u32>> = vec![None; 4];
+    let mut entry_line = None;
+    for (line_number, line) in index.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "[[tiles]]" {
+            if let Some(entry_line) = entry_line {
+                entries.push(create_entry(path, &values, entries.len(), entry_line)?);
+            }
+            values = vec![None; 4];
+            entry_line = Some(line_number);
+            continue;
+        }
+        let (key, value) = match line.find('=') {
+            Some(position) if entry_line.is_some() => (line[..position].trim(), line[position + 1..].trim()),
+            _ => return Err(Error::syntax(path, line_number, "expected [[tiles]] or key = value")),
+        };
+        let value = match value.parse() {
+            Ok(value) => value,
+            Err(_) => return Err(Error::syntax(path, line_number, &format!("invalid number {}", value))),
+        };
+        match key {
+            "x" => values[0] = Some(value),
+            "y" => values[1] = Some(value),
+            "width" => values[2] = Some(value),
+            "height" => values[3] = Some(value),
+            _ => return Err(Error::syntax(path, line_number, &format!("unknown key {}", key))),
+        }
+    }
+    if let Some(entry_line) = entry_line {
+        entries.push(create_entry(path, &values, entries.len(), entry_line)?);
+    }
+    Ok(entries)
+}
+
+
+/// Creates the entry of a tile from its values, `line_number` is the line of
+/// its `[[tiles]]` header.
+fn create_entry(path: &Path, values: &[Option<u32>], tile_number: usize, line_number: usize) -> Result<TileEntry, Error> {
+    match values {
+        &[Some(x), Some(y), Some(width), Some(height)] if width <= u16::MAX as u32 && height <= u16::MAX as u32 => {
+            Ok(TileEntry { x, y, width: width as u16, height: height as u16 })
+        }
+        _ => {
+            let message = format!("tile {} needs x, y, width and height", tile_number);
+            Err(Error::syntax(path, line_number, &message))
+        }
+    }
+}
+
+
+/// Writes the tiles as an RGBA sprite sheet and the positions and sizes of the
+/// tiles to the index file.  Palette index 0 is transparent.
+pub fn export(tiles: &[Tile], palette: &[Vec<u8>], png_path: &Path, index_path: &Path) -> Result<(), Error> {
+    let (entries, width, height) = layout(tiles);
+    let mut image = vec![0u8; width as usize * height as usize * 4];
+    for (tile, entry) in tiles.iter().zip(&entries) {
+        for (i, &color_index) in tile.data.iter().enumerate() {
+            let x = entry.x as usize + i % tile.width as usize;
+            let y = entry.y as usize + i / tile.width as usize;
+            let color = match palette.get(color_index as usize) {
+                Some(color) => color,
+                None => return Err(Error::MissingColor { color_index }),
+            };
+            let offset = (y * width as usize + x) * 4;
+            image[offset..offset + 4].copy_from_slice(&color[..4]);
+        }
+    }
+
+    let file = match File::create(png_path) {
+        Ok(file) => file,
+        Err(reason) => return Err(Error::io(png_path, reason)),
+    };
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let result = encoder.write_header().and_then(|mut writer| writer.write_image_data(&image));
+    if let Err(reason) = result {
+        return Err(Error::Png { path: png_path.into(), message: reason.to_string() });
+    }
+    fs::write(index_path, serialize_index(&entries)).map_err(|reason| Error::io(index_path, reason))
+}
+
+
+/// Reads the tiles back from an edited sprite sheet.  Every pixel is mapped to
+/// the nearest palette color, pixels that are more than half transparent to
+/// palette index 0.
+pub fn import(png_path: &Path, index_path: &Path, palette: &[Vec<u8>]) -> Result<Vec<Tile>, Error> {
+    let index = match fs::read_to_string(index_path) {
+        Ok(index) => index,
+        Err(reason) => return Err(Error::io(index_path, reason)),
+    };
+    let entries = parse_index(index_path, &index)?;
+    let (image, width, height) = read_png(png_path)?;
+
+    let mut tiles = vec![];
+    for (tile_number, entry) in entries.iter().enumerate() {
+        if entry.x + entry.width as u32 > width || entry.y + entry.height as u32 > height {
+            return Err(Error::TileOutsideSheet { path: png_path.into(), tile_number });
+        }
+        let mut data = Vec::with_capacity(entry.width as usize * entry.height as usize);
+        for y in entry.y..entry.y + entry.height as u32 {
+            for x in entry.x..entry.x + entry.width as u32 {
+                let offset = (y as usize * width as usize + x as usize) * 4;
+                data.push(nearest_color(palette, &image[offset..offset + 4]));
+            }
+        }
+        tiles.push(Tile { width: entry.width, height: entry.height, data });
+    }
+    Ok(tiles)
+}
+
+
+/// Returns the image as RGBA pixels with its width and height.
+fn read_png(path: &Path) -> Result<(Vec<u8>, u32, u32), Error> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(reason) => return Err(Error::io(path, reason)),
+    };
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = match decoder.read_info() {
+        Ok(reader) => reader,
+        Err(reason) => return Err(Error::Png { path: path.into(), message: reason.to_string() }),
+    };
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = match reader.next_frame(&mut buffer) {
+        Ok(info) => info,
+        Err(reason) => return Err(Error::Png { path: path.into(), message: reason.to_string() }),
+    };
+    let pixels = &buffer[..info.buffer_size()];
+    let image = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks_exact(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&p| vec![p, p, p, 255]).collect(),
+        png::ColorType::Indexed => {
+            return Err(Error::Png { path: path.into(), message: "unsupported color type".into() });
+        }
+    };
+    Ok((image, info.width, info.height))
+}
+
+
+fn nearest_color(palette: &[Vec<u8>], pixel: &[u8]) -> u8 {
+    if pixel[3] < 128 {
+        return 0;
+    }
+    let distance = |color: &Vec<u8>| {
+        (0..3).map(|i| (color[i] as i32 - pixel[i] as i32).pow(2)).sum::<i32>()
+    };
+    palette.iter().enumerate().skip(1)
+        .min_by_key(|(_, color)| distance(color))
+        .map_or(0, |(i, _)| i as u8)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn palette() -> Vec<Vec<u8>> {
+        vec![vec![0, 0, 0, 0], vec![0, 0, 0, 255], vec![252, 0, 0, 255], vec![0, 252, 0, 255], vec![0, 0, 252, 255]]
+    }
+
+    #[test]
+    fn test_index() {
+        let entries = vec![
+            TileEntry { x: 0, y: 0, width: 20, height: 20 },
+            TileEntry { x: 20, y: 0, width: 10, height: 15 },
+        ];
+        let index = serialize_index(&entries);
+        assert!(index.starts_with("[[tiles]]\nx = 0\ny = 0\nwidth = 20\nheight = 20\n"));
+        let path = Path::new("tiles.toml");
+        assert_eq!(parse_index(path, &index).unwrap(), entries);
+        assert_eq!(parse_index(path, "# no tiles\n").unwrap(), vec![]);
+        assert_eq!(parse_index(path, "x = 1").unwrap_err().to_string(), "tiles.toml:1: expected [[tiles]] or key = value");
+        assert!(matches!(
+            parse_index(path, "[[tiles]]\nx = 1\ny = 2\nwidth = 3"),
+            Err(Error::Syntax { line: 1, message, .. }) if message == "tile 0 needs x, y, width and height"
+        ));
+        assert!(matches!(
+            parse_index(path, "[[tiles]]\ndepth = 1"),
+            Err(Error::Syntax { line: 2, message, .. }) if message == "unknown key depth"
+        ));
+    }
+
+    #[test]
+    fn test_layout() {
+        let tiles: Vec<Tile> = (0..18).map(|i| Tile { width: 2 + i % 3, height: 3, data: vec![0; 3 * (2 + i as usize % 3)] }).collect();
+        let (entries, width, height) = layout(&tiles);
+        assert_eq!((width, height), (16 * 4, 2 * 3));
+        assert_eq!(entries[1], TileEntry { x: 4, y: 0, width: 3, height: 3 });
+        assert_eq!(entries[17], TileEntry { x: 4, y: 3, width: 4, height: 3 });
+    }
+
+    #[test]
+    fn test_nearest_color() {
+        let palette = palette();
+        assert_eq!(nearest_color(&palette, &[252, 0, 0, 255]), 2);
+        assert_eq!(nearest_color(&palette, &[200, 30, 10, 255]), 2);
+        assert_eq!(nearest_color(&palette, &[0, 0, 0, 255]), 1);
+        assert_eq!(nearest_color(&palette, &[0, 0, 252, 0]), 0);
+    }
+
+    #[test]
+    fn test_export_import() {
+        let tiles = vec![
+            Tile { width: 2, height: 2, data: vec![0, 1, 2, 3] },
+            Tile { width: 3, height: 1, data: vec![4, 4, 0] },
+        ];
+        let directory = tempfile::tempdir().unwrap();
+        let png_path = directory.path().join("tiles.png");
+        let index_path = directory.path().join("tiles.toml");
+        export(&tiles, &palette(), &png_path, &index_path).unwrap();
+        assert_eq!(import(&png_path, &index_path, &palette()).unwrap(), tiles);
+    }
+}
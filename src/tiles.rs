@@ -1,38 +1,70 @@
+//! The tile file (`TILE.DAT`) with the palette-indexed images of all tiles.
+
 use std::convert::TryInto;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
 use std::path::Path;
 
+use crate::error::Error;
+
+/// A palette-indexed image of `width` x `height` pixels.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Tile {
+    /// The width in pixels.
     pub width: u16,
+    /// The height in pixels.
     pub height: u16,
+    /// The palette indices of the pixels, row by row.
     pub data: Vec<u8>,
 }
 
 
-pub fn load_tiles(path: &Path) -> Result<Vec<Tile>, String> {
-    let mut file = File::open(path).unwrap();
-    let mut buffer = vec!();
-    file.read_to_end(&mut buffer).unwrap();
-    let buffer = &buffer[..];
+/// Reads all tiles of the tile file.  Each tile is stored as its width and
+/// height (16-bit little endian) followed by its pixels.
+pub fn load_tiles(path: &Path) -> Result<Vec<Tile>, Error> {
+    let buffer = match fs::read(path) {
+        Ok(buffer) => buffer,
+        Err(reason) => return Err(Error::io(path, reason)),
+    };
     let mut tiles = vec!();
     let mut i = 0;
     while i < buffer.len() {
+        if i + 4 > buffer.len() {
+            return Err(Error::TruncatedHeader { path: path.into(), offset: i });
+        }
         let width = u16::from_le_bytes(buffer[i..i + 2].try_into().unwrap());
         let height = u16::from_le_bytes(buffer[i + 2..i + 4].try_into().unwrap());
-        if i + 4 + width as usize * height as usize > buffer.len() {
-            return Err("cannot read over array bounds".into());
+        let size = width as usize * height as usize;
+        if i + 4 + size > buffer.len() {
+            return Err(Error::SizeMismatch { path: path.into(), offset: i, expected: 4 + size, found: buffer.len() - i });
         }
         tiles.push(Tile {
             width,
             height,
-            data: buffer[i + 4..i + 4 + width as usize * height as usize].into(),
+            data: buffer[i + 4..i + 4 + size].into(),
         });
-        i += 4 + width as usize * height as usize;
+        i += 4 + size;
     }
     Ok(tiles)
 }
 
+
+fn serialize_tiles(tiles: &[Tile]) -> Vec<u8> {
+    let mut buffer = vec![];
+    for tile in tiles {
+        buffer.extend_from_slice(&tile.width.to_le_bytes());
+        buffer.extend_from_slice(&tile.height.to_le_bytes());
+        buffer.extend_from_slice(&tile.data);
+    }
+    buffer
+}
+
+
+/// Writes the tiles in the format `load_tiles` reads.
+pub fn save_tiles(path: &Path, tiles: &[Tile]) -> Result<(), Error> {
+    fs::write(path, serialize_tiles(tiles)).map_err(|reason| Error::io(path, reason))
+}
+
+
 #[cfg(test)]
 mod test {
     use std::io::Write;
@@ -83,4 +115,29 @@ mod test {
         assert_eq!(tiles[3].height, 20);
         assert_eq!(tiles[3].data.len(), 20 * 20);
     }
+
+    #[test]
+    fn test_save() {
+        let tiles = vec![
+            Tile { width: 2, height: 3, data: vec![1, 2, 3, 4, 5, 6] },
+            Tile { width: 1, height: 1, data: vec![7] },
+        ];
+        let path = NamedTempFile::new().unwrap().into_temp_path();
+        save_tiles(&path, &tiles).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), vec![2, 0, 3, 0, 1, 2, 3, 4, 5, 6, 1, 0, 1, 0, 7]);
+        assert_eq!(load_tiles(&path).unwrap(), tiles);
+    }
+
+    #[test]
+    fn test_load_corrupted() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&[1, 0, 1, 0, 7, 2, 0]).unwrap();
+        let path = file.into_temp_path();
+        assert!(matches!(load_tiles(&path), Err(Error::TruncatedHeader { offset: 5, .. })));
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&[1, 0, 1, 0, 7, 2, 0, 2, 0, 1, 2]).unwrap();
+        let path = file.into_temp_path();
+        assert!(matches!(load_tiles(&path), Err(Error::SizeMismatch { offset: 5, expected: 8, found: 6, .. })));
+    }
 }